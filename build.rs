@@ -0,0 +1,11 @@
+use std::process::Command;
+
+/// Sets `has_nasm` when nasm can be run, so tests that execute the generated
+/// code are reported as ignored without it.
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(has_nasm)");
+    println!("cargo::rerun-if-env-changed=PATH");
+    if Command::new("nasm").arg("-v").output().is_ok() {
+        println!("cargo::rustc-cfg=has_nasm");
+    }
+}
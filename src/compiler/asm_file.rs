@@ -18,18 +18,25 @@ impl AsmFile {
     global main
     extern printf
     extern exit
-main:
 "#,
             ),
         };
 
         gen.write_data("__aeiou__format_i32 db \"%d\", 10, 0");
+        gen.write_data("__aeiou__format_i64 db \"%ld\", 10, 0");
         gen.write_data("__aeiou__format_f64 db \"%f\", 10, 0");
-        gen.write_text("push rbp");
-        gen.write_text("mov rbp, rsp");
+        gen.write_data("__aeiou__format_str db \"%s\", 10, 0");
+        gen.write_data("__aeiou__true db \"ჭეშმარიტი\", 0");
+        gen.write_data("__aeiou__false db \"მცდარი\", 0");
         gen
     }
 
+    pub fn begin_function(&mut self, label: &str) {
+        writeln!(self.text_section, "{}:", label).unwrap();
+        self.write_text("push rbp");
+        self.write_text("mov rbp, rsp");
+    }
+
     pub fn write_rodata(&mut self, input: &str) {
         writeln!(self.rodata_section, "\t{}", input).unwrap();
     }
@@ -38,6 +45,7 @@ main:
         writeln!(self.data_section, "\t{}", input).unwrap();
     }
 
+    #[allow(dead_code)]
     pub fn write_bss(&mut self, input: &str) {
         writeln!(self.bss_section, "\t{}", input).unwrap();
     }
@@ -55,14 +63,6 @@ main:
     }
 
     pub fn finalize(&mut self) -> String {
-        self.write_text(
-            r#"
-	leave
-	mov rdi, 0
-	call exit
-	ret
-	"#,
-        );
         format!(
             "{}\n{}\n{}\n{}",
             self.rodata_section, self.data_section, self.bss_section, self.text_section
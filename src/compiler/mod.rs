@@ -1,19 +1,46 @@
 mod asm_file;
 mod register;
+mod symbol;
 
 use crate::compiler::asm_file::AsmFile;
-use crate::compiler::register::Register::{Rax, Rdi, Rdx, Rsi, Xmm0};
-use crate::compiler::register::{Register, RegisterAllocator};
-use crate::lexer::TokenType;
+use crate::compiler::register::Register::{Rax, Rbx, Rcx, Rdi, Rdx, Rsi, Xmm0, R12};
+use crate::compiler::register::{
+    Register, RegisterAllocator, ARGUMENT_REGISTERS, ARGUMENT_REGISTERS_SIMD,
+};
+use crate::compiler::symbol::SymbolTable;
+use crate::diagnostic::{Diagnostic, ErrorKind};
+use crate::lexer::{Span, TokenType};
 use crate::parser::{Expr, Stmt, Type};
 use std::collections::HashMap;
-use std::error::Error;
-use std::fs;
+
+struct Loop {
+    start: String,
+    end: String,
+    stack_size: usize,
+}
+
+/// Exit status of a program stopped by a failed runtime check.
+pub const RUNTIME_ERROR_STATUS: i32 = 101;
+
+const OVERFLOW: &str = "__aeiou__overflow";
+const DIVISION_BY_ZERO: &str = "__aeiou__division_by_zero";
 
 pub struct Compiler {
     reg_alloc: RegisterAllocator,
     asm_file: AsmFile,
     literals: HashMap<String, String>,
+    symbols: SymbolTable,
+    label_count: usize,
+    loops: Vec<Loop>,
+    functions: HashMap<String, String>,
+    stack_cells: Vec<bool>,
+    checked: bool,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Compiler {
@@ -22,29 +49,62 @@ impl Compiler {
             reg_alloc: RegisterAllocator::new(),
             asm_file: AsmFile::new(),
             literals: HashMap::new(),
+            symbols: SymbolTable::new(),
+            label_count: 0,
+            loops: Vec::new(),
+            functions: HashMap::new(),
+            stack_cells: Vec::new(),
+            checked: false,
         }
     }
 
+    /// Enables runtime checks for integer overflow and division by zero.
+    pub fn checked(mut self, checked: bool) -> Compiler {
+        self.checked = checked;
+        self
+    }
+
     fn get_mov_variation(t: &Type) -> &str {
         match t {
-            Type::I32 => "mov",
+            Type::I32 | Type::I64 | Type::Bool | Type::Str => "mov",
             Type::F64 => "movsd",
         }
     }
 
+    /// The name of `reg` at the width `t` is computed in: `i32` arithmetic is
+    /// done on 32-bit sub-registers so that it wraps around like an `i32`.
+    fn operand(t: &Type, reg: &Register) -> String {
+        match t {
+            Type::I32 | Type::Bool => reg.dword(),
+            Type::I64 | Type::F64 | Type::Str => reg.to_string(),
+        }
+    }
+
     fn store_literal(&mut self, t: &Type, lit: &str) -> String {
         let typ = match t {
-            Type::I32 => "dd",
-            Type::F64 => "dq",
+            Type::I32 | Type::Bool => "dd",
+            Type::I64 | Type::F64 => "dq",
+            Type::Str => "db",
         };
         let key = format!("{}|{}", typ, lit);
         if let Some(literal) = self.literals.get(&key) {
             return literal.clone();
         }
 
+        let value = match t {
+            // Strings are written byte by byte so that escapes and UTF-8 text
+            // don't depend on how the assembler treats quoted strings.
+            Type::Str => lit
+                .bytes()
+                .chain([0])
+                .map(|byte| byte.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            _ => lit.to_string(),
+        };
         let literal = format!("__aeiou__literal__{}", self.literals.len());
         self.asm_file
-            .write_rodata(&format!("{} {} {}", literal, typ, lit));
+            .write_rodata(&format!("{} {} {}", literal, typ, value));
         self.literals.insert(key, literal.clone());
         literal
     }
@@ -52,32 +112,53 @@ impl Compiler {
     fn mov_l2r(&mut self, t: &Type, dst: &Register, literal: &str) {
         let instruction = Self::get_mov_variation(t);
         self.asm_file
-            .write_instruction2(instruction, &dst.to_string(), literal);
+            .write_instruction2(instruction, &Self::operand(t, dst), literal);
     }
 
     fn mov_m2r(&mut self, t: &Type, dst: &Register, memory: &str) {
-        let instruction = match t {
-            Type::I32 => "movsxd",
-            Type::F64 => "movsd",
+        let (instruction, size) = match t {
+            Type::I32 | Type::Bool => ("mov", "dword"),
+            Type::I64 | Type::Str => ("mov", "qword"),
+            Type::F64 => ("movsd", "qword"),
         };
-        self.asm_file
-            .write_instruction2(instruction, &dst.to_string(), &format!("[{}]", memory));
+        self.asm_file.write_instruction2(
+            instruction,
+            &Self::operand(t, dst),
+            &format!("{} [{}]", size, memory),
+        );
+    }
+
+    fn mov_r2m(&mut self, t: &Type, memory: &str, src: &Register) {
+        let (instruction, size) = match t {
+            Type::I32 | Type::Bool => ("mov", "dword"),
+            Type::I64 | Type::Str => ("mov", "qword"),
+            Type::F64 => ("movsd", "qword"),
+        };
+        self.asm_file.write_instruction2(
+            instruction,
+            &format!("{} [{}]", size, memory),
+            &Self::operand(t, src),
+        );
     }
 
     fn mov_r2r(&mut self, t: &Type, dst: &Register, src: &Register) {
         let instruction = Self::get_mov_variation(t);
-        self.asm_file
-            .write_instruction2(instruction, &dst.to_string(), &src.to_string());
+        self.asm_file.write_instruction2(
+            instruction,
+            &Self::operand(t, dst),
+            &Self::operand(t, src),
+        );
     }
 
     fn neg(&mut self, t: &Type, dst: &Register) {
         match t {
-            Type::I32 => {
-                self.asm_file.write_instruction1("neg", &dst.to_string());
+            Type::I32 | Type::I64 | Type::Bool | Type::Str => {
+                self.asm_file
+                    .write_instruction1("neg", &Self::operand(t, dst));
             }
             Type::F64 => {
                 let neg_literal = self.store_literal(t, "-1.0");
-                let temp_temp = self.reg_alloc.alloc(t);
+                let temp_temp = self.alloc(t);
                 self.mov_m2r(t, &temp_temp, &neg_literal);
                 self.asm_file
                     .write_instruction2("mulsd", &dst.to_string(), &temp_temp.to_string());
@@ -88,37 +169,50 @@ impl Compiler {
 
     fn add(&mut self, t: &Type, dst: &Register, src: &Register) {
         let instruction = match t {
-            Type::I32 => "add",
+            Type::I32 | Type::I64 | Type::Bool | Type::Str => "add",
             Type::F64 => "addsd",
         };
-        self.asm_file
-            .write_instruction2(instruction, &dst.to_string(), &src.to_string());
+        self.asm_file.write_instruction2(
+            instruction,
+            &Self::operand(t, dst),
+            &Self::operand(t, src),
+        );
     }
 
     fn sub(&mut self, t: &Type, dst: &Register, src: &Register) {
         let instruction = match t {
-            Type::I32 => "sub",
+            Type::I32 | Type::I64 | Type::Bool | Type::Str => "sub",
             Type::F64 => "subsd",
         };
-        self.asm_file
-            .write_instruction2(instruction, &dst.to_string(), &src.to_string());
+        self.asm_file.write_instruction2(
+            instruction,
+            &Self::operand(t, dst),
+            &Self::operand(t, src),
+        );
     }
 
     fn mul(&mut self, t: &Type, dst: &Register, src: &Register) {
         let instruction = match t {
-            Type::I32 => "imul",
+            Type::I32 | Type::I64 | Type::Bool | Type::Str => "imul",
             Type::F64 => "mulsd",
         };
-        self.asm_file
-            .write_instruction2(instruction, &dst.to_string(), &src.to_string());
+        self.asm_file.write_instruction2(
+            instruction,
+            &Self::operand(t, dst),
+            &Self::operand(t, src),
+        );
     }
 
     fn div(&mut self, t: &Type, dst: &Register, src: &Register) {
         match t {
-            Type::I32 => {
-                self.mov_l2r(t, &Rdx, "0");
+            Type::I32 | Type::I64 | Type::Bool | Type::Str => {
                 self.mov_r2r(t, &Rax, dst);
-                self.asm_file.write_instruction1("idiv", &src.to_string());
+                match t {
+                    Type::I64 => self.asm_file.write_text("cqo"),
+                    _ => self.asm_file.write_text("cdq"),
+                }
+                self.asm_file
+                    .write_instruction1("idiv", &Self::operand(t, src));
                 self.mov_r2r(t, dst, &Rax);
             }
             Type::F64 => {
@@ -128,93 +222,919 @@ impl Compiler {
         }
     }
 
+    fn rem(&mut self, t: &Type, dst: &Register, src: &Register) {
+        match t {
+            Type::F64 => {
+                // `dst - trunc(dst / src) * src`, which keeps the sign of the
+                // dividend like C's `fmod`.
+                self.mov_r2r(t, &Xmm0, dst);
+                self.asm_file
+                    .write_instruction2("divsd", &Xmm0.to_string(), &src.to_string());
+                self.asm_file.write_text("roundsd xmm0, xmm0, 3");
+                self.asm_file
+                    .write_instruction2("mulsd", &Xmm0.to_string(), &src.to_string());
+                self.asm_file
+                    .write_instruction2("subsd", &dst.to_string(), &Xmm0.to_string());
+            }
+            _ => {
+                self.div(t, dst, src);
+                self.mov_r2r(t, dst, &Rdx);
+            }
+        }
+    }
+
+    fn bitwise(&mut self, t: &Type, op: &TokenType, dst: &Register, src: &Register) {
+        let instruction = match op {
+            TokenType::Ampersand => "and",
+            TokenType::Pipe => "or",
+            _ => "xor",
+        };
+        self.asm_file.write_instruction2(
+            instruction,
+            &Self::operand(t, dst),
+            &Self::operand(t, src),
+        );
+    }
+
+    fn shift(&mut self, t: &Type, op: &TokenType, dst: &Register, src: &Register) {
+        let instruction = match op {
+            TokenType::LessLess => "shl",
+            _ => "sar",
+        };
+        // The count has to be in `cl`, but `rcx` is handed out by the
+        // allocator, so it is parked in `rdx` while the shift happens in `rax`.
+        self.asm_file
+            .write_instruction2("mov", &Rdx.to_string(), &Rcx.to_string());
+        self.mov_r2r(t, &Rax, dst);
+        self.asm_file
+            .write_instruction2("mov", &Rcx.to_string(), &src.to_string());
+        self.asm_file
+            .write_instruction2(instruction, &Self::operand(t, &Rax), &Rcx.byte());
+        self.asm_file
+            .write_instruction2("mov", &Rcx.to_string(), &Rdx.to_string());
+        self.mov_r2r(t, dst, &Rax);
+    }
+
+    /// Raises `dst` to the `i32` power in `exponent` by repeated squaring. A
+    /// negative exponent gives `1 / dst ** -exponent`.
+    fn power(&mut self, t: &Type, dst: &Register, exponent: &Register, span: Span) {
+        let result = match t {
+            Type::F64 => Xmm0,
+            _ => Rax,
+        };
+        let one = self.store_literal(t, if *t == Type::F64 { "1.0" } else { "1" });
+        let multiply = match t {
+            Type::F64 => "mulsd",
+            _ => "imul",
+        };
+        let (start, skip, done, store) = (
+            self.new_label(),
+            self.new_label(),
+            self.new_label(),
+            self.new_label(),
+        );
+        let counter = Rdx.dword();
+        let exponent = exponent.dword();
+
+        self.mov_m2r(t, &result, &one);
+        self.asm_file.write_instruction2("mov", &counter, &exponent);
+        self.asm_file.write_instruction2("test", &counter, &counter);
+        self.asm_file.write_instruction1("jns", &start);
+        self.asm_file.write_instruction1("neg", &counter);
+        self.asm_file.write_instruction2("test", &counter, &counter);
+        self.asm_file.write_instruction1("jz", &done);
+        self.label(&start);
+        self.asm_file.write_instruction2("test", &counter, "1");
+        self.asm_file.write_instruction1("jz", &skip);
+        self.asm_file.write_instruction2(
+            multiply,
+            &Self::operand(t, &result),
+            &Self::operand(t, dst),
+        );
+        self.check_overflow(t, span);
+        self.label(&skip);
+        // The base is only squared while bits remain, so a checked overflow
+        // here always affects the result.
+        self.asm_file.write_instruction2("shr", &counter, "1");
+        self.asm_file.write_instruction1("jz", &done);
+        self.asm_file
+            .write_instruction2(multiply, &Self::operand(t, dst), &Self::operand(t, dst));
+        self.check_overflow(t, span);
+        self.asm_file.write_instruction1("jmp", &start);
+        self.label(&done);
+        self.asm_file
+            .write_instruction2("test", &exponent, &exponent);
+        self.asm_file.write_instruction1("jns", &store);
+        self.mov_r2r(t, dst, &result);
+        self.mov_m2r(t, &result, &one);
+        match t {
+            Type::F64 => {
+                self.asm_file
+                    .write_instruction2("divsd", &result.to_string(), &dst.to_string())
+            }
+            _ => {
+                self.check_zero(t, dst, span);
+                match t {
+                    Type::I64 => self.asm_file.write_text("cqo"),
+                    _ => self.asm_file.write_text("cdq"),
+                }
+                self.asm_file
+                    .write_instruction1("idiv", &Self::operand(t, dst));
+            }
+        }
+        self.label(&store);
+        self.mov_r2r(t, dst, &result);
+    }
+
+    /// Jumps to the runtime error `routine` with the line of `span` unless the
+    /// flags satisfy the conditional jump `skip`.
+    fn guard(&mut self, skip: &str, routine: &str, span: Span) {
+        let ok = self.new_label();
+        self.asm_file.write_instruction1(skip, &ok);
+        self.asm_file
+            .write_instruction2("mov", &Rdi.dword(), &span.line.to_string());
+        self.asm_file.write_instruction1("jmp", routine);
+        self.label(&ok);
+    }
+
+    fn check_overflow(&mut self, t: &Type, span: Span) {
+        if self.checked && t.is_integer() {
+            self.guard("jno", OVERFLOW, span);
+        }
+    }
+
+    fn check_zero(&mut self, t: &Type, divisor: &Register, span: Span) {
+        if self.checked && t.is_integer() {
+            let divisor = Self::operand(t, divisor);
+            self.asm_file.write_instruction2("test", &divisor, &divisor);
+            self.guard("jnz", DIVISION_BY_ZERO, span);
+        }
+    }
+
+    /// Guards an `idiv` against a zero divisor and against the one quotient
+    /// that overflows, the minimum value divided by -1.
+    fn check_division(&mut self, t: &Type, dividend: &Register, divisor: &Register, span: Span) {
+        if !(self.checked && t.is_integer()) {
+            return;
+        }
+        self.check_zero(t, divisor, span);
+        let ok = self.new_label();
+        self.asm_file
+            .write_instruction2("cmp", &Self::operand(t, divisor), "-1");
+        self.asm_file.write_instruction1("jne", &ok);
+        // Negating the dividend overflows exactly when it is the minimum.
+        self.mov_r2r(t, &Rax, dividend);
+        self.asm_file
+            .write_instruction1("neg", &Self::operand(t, &Rax));
+        self.guard("jno", OVERFLOW, span);
+        self.label(&ok);
+    }
+
+    /// Emits the routines that failed runtime checks jump to, with the line
+    /// number of the failing operation in `edi`.
+    fn runtime_errors(&mut self) {
+        self.asm_file
+            .write_data("__aeiou__format_error db \"შეცდომა ხაზზე %d: %s\", 10, 0");
+        self.asm_file
+            .write_data("__aeiou__overflow_message db \"არითმეტიკული გადავსება\", 0");
+        self.asm_file
+            .write_data("__aeiou__division_by_zero_message db \"ნულზე გაყოფა\", 0");
+
+        for (routine, message) in [
+            (OVERFLOW, "__aeiou__overflow_message"),
+            (DIVISION_BY_ZERO, "__aeiou__division_by_zero_message"),
+        ] {
+            self.label(routine);
+            self.asm_file
+                .write_instruction2("mov", &Rsi.to_string(), message);
+            self.asm_file
+                .write_instruction1("jmp", "__aeiou__runtime_error");
+        }
+
+        self.asm_file.write_text("extern fflush");
+        self.asm_file.write_text("extern dprintf");
+        self.label("__aeiou__runtime_error");
+        // Nothing is returned to, so callee-saved registers can hold the line
+        // and message across the calls.
+        self.asm_file
+            .write_instruction2("mov", &Rbx.dword(), &Rdi.dword());
+        self.asm_file
+            .write_instruction2("mov", &R12.to_string(), &Rsi.to_string());
+        // Checks can fail at any stack depth.
+        self.asm_file.write_instruction2("and", "rsp", "-16");
+        // Flush what the program printed so far, so the error comes after it.
+        self.mov_l2r(&Type::I32, &Rdi, "0");
+        self.asm_file.write_instruction1("call", "fflush");
+        // Standard error, so the message doesn't mix with the program's output.
+        self.mov_l2r(&Type::I32, &Rdi, "2");
+        self.asm_file
+            .write_instruction2("mov", &Rsi.to_string(), "__aeiou__format_error");
+        self.asm_file
+            .write_instruction2("mov", &Rdx.dword(), &Rbx.dword());
+        self.asm_file
+            .write_instruction2("mov", &Rcx.to_string(), &R12.to_string());
+        self.mov_l2r(&Type::I32, &Rax, "0");
+        self.asm_file.write_instruction1("call", "dprintf");
+        self.mov_l2r(&Type::I32, &Rdi, &RUNTIME_ERROR_STATUS.to_string());
+        self.asm_file.write_instruction1("call", "exit");
+    }
+
+    /// Converts the value on top of `from`'s register stack to `to`.
+    fn cast(&mut self, from: &Type, to: &Type) {
+        match (from, to) {
+            (Type::F64, Type::F64) => {}
+            (_, Type::F64) => {
+                let src = self.reg_alloc.dealloc(from);
+                let dst = self.alloc(to);
+                self.asm_file.write_instruction2(
+                    "cvtsi2sd",
+                    &dst.to_string(),
+                    &Self::operand(from, &src),
+                );
+            }
+            (Type::F64, _) => {
+                let src = self.reg_alloc.dealloc(from);
+                let dst = self.alloc(to);
+                self.asm_file.write_instruction2(
+                    "cvttsd2si",
+                    &Self::operand(to, &dst),
+                    &src.to_string(),
+                );
+            }
+            (Type::I32, Type::I64) => {
+                let dst = self.reg_alloc.peek(from);
+                self.asm_file
+                    .write_instruction2("movsxd", &dst.to_string(), &dst.dword());
+            }
+            // Booleans are already zero-extended, and narrowing to `i32` only
+            // drops the upper half, which 32-bit instructions ignore.
+            _ => {}
+        }
+    }
+
+    fn compare(&mut self, t: &Type, op: &TokenType, span: Span) -> Result<(), Diagnostic> {
+        let dst = match t {
+            Type::I32 | Type::I64 | Type::Bool | Type::Str => {
+                let src = self.reg_alloc.dealloc(t);
+                let dst = self.reg_alloc.peek(t);
+                let condition = match op {
+                    TokenType::EqualEqual => "sete",
+                    TokenType::BangEqual => "setne",
+                    TokenType::Less => "setl",
+                    TokenType::LessEqual => "setle",
+                    TokenType::Greater => "setg",
+                    TokenType::GreaterEqual => "setge",
+                    _ => {
+                        return Err(Diagnostic::error(
+                            ErrorKind::Codegen,
+                            span,
+                            "Invalid operator.",
+                        ))
+                    }
+                };
+                self.asm_file.write_instruction2(
+                    "cmp",
+                    &Self::operand(t, &dst),
+                    &Self::operand(t, &src),
+                );
+                self.asm_file.write_instruction1(condition, &dst.byte());
+                dst
+            }
+            Type::F64 => {
+                let right = self.reg_alloc.dealloc(t);
+                let left = self.reg_alloc.dealloc(t);
+                let dst = self.alloc(&Type::Bool);
+                // `ucomisd` reports unordered (NaN) operands through PF and CF, so
+                // `<`/`<=` swap operands to reuse the "above" conditions, which are
+                // false for NaN, and `==`/`!=` fold in the parity flag explicitly.
+                let (first, second) = match op {
+                    TokenType::Less | TokenType::LessEqual => (&right, &left),
+                    _ => (&left, &right),
+                };
+                self.asm_file.write_instruction2(
+                    "ucomisd",
+                    &first.to_string(),
+                    &second.to_string(),
+                );
+                match op {
+                    TokenType::EqualEqual => {
+                        self.asm_file.write_instruction1("sete", &dst.byte());
+                        self.asm_file.write_instruction1("setnp", &Rax.byte());
+                        self.asm_file
+                            .write_instruction2("and", &dst.byte(), &Rax.byte());
+                    }
+                    TokenType::BangEqual => {
+                        self.asm_file.write_instruction1("setne", &dst.byte());
+                        self.asm_file.write_instruction1("setp", &Rax.byte());
+                        self.asm_file
+                            .write_instruction2("or", &dst.byte(), &Rax.byte());
+                    }
+                    TokenType::Less | TokenType::Greater => {
+                        self.asm_file.write_instruction1("seta", &dst.byte())
+                    }
+                    TokenType::LessEqual | TokenType::GreaterEqual => {
+                        self.asm_file.write_instruction1("setae", &dst.byte())
+                    }
+                    _ => {
+                        return Err(Diagnostic::error(
+                            ErrorKind::Codegen,
+                            span,
+                            "Invalid operator.",
+                        ))
+                    }
+                }
+                dst
+            }
+        };
+        self.asm_file
+            .write_instruction2("movzx", &dst.dword(), &dst.byte());
+        Ok(())
+    }
+
+    /// Prints the value on top of `t`'s register stack, consuming it.
     fn print(&mut self, t: &Type) {
-        let s = &self.reg_alloc.peek(t);
+        let s = &self.reg_alloc.dealloc(t);
+        self.save_live();
+        // The value is moved out before `rdi` is loaded, since it may live there.
         match t {
             Type::I32 => {
+                self.mov_r2r(t, &Rsi, s);
                 self.asm_file
                     .write_instruction2("mov", &Rdi.to_string(), "__aeiou__format_i32");
+                self.mov_l2r(t, &Rax, "0");
+            }
+            Type::I64 => {
                 self.mov_r2r(t, &Rsi, s);
+                self.asm_file
+                    .write_instruction2("mov", &Rdi.to_string(), "__aeiou__format_i64");
                 self.mov_l2r(t, &Rax, "0");
             }
             Type::F64 => {
+                self.mov_r2r(t, &Xmm0, s);
                 self.asm_file
                     .write_instruction2("mov", &Rdi.to_string(), "__aeiou__format_f64");
-                self.mov_r2r(t, &Xmm0, s);
-                self.mov_l2r(t, &Rax, "1");
+                self.mov_l2r(&Type::I32, &Rax, "1");
+            }
+            Type::Bool => {
+                self.asm_file
+                    .write_instruction2("mov", &Rax.to_string(), "__aeiou__true");
+                self.asm_file
+                    .write_instruction2("mov", &Rdx.to_string(), "__aeiou__false");
+                self.asm_file
+                    .write_instruction2("test", &s.dword(), &s.dword());
+                self.asm_file
+                    .write_instruction2("cmovz", &Rax.to_string(), &Rdx.to_string());
+                self.asm_file
+                    .write_instruction2("mov", &Rsi.to_string(), &Rax.to_string());
+                self.asm_file
+                    .write_instruction2("mov", &Rdi.to_string(), "__aeiou__format_str");
+                self.mov_l2r(t, &Rax, "0");
+            }
+            Type::Str => {
+                self.mov_r2r(t, &Rsi, s);
+                self.asm_file
+                    .write_instruction2("mov", &Rdi.to_string(), "__aeiou__format_str");
+                self.mov_l2r(t, &Rax, "0");
+            }
+        }
+        self.call_aligned("printf");
+    }
+
+    fn var(&mut self, t: &Type, name: &str) {
+        let src = self.reg_alloc.peek(t);
+        let symbol = self.symbols.declare(name, t);
+        self.asm_file.write_instruction2("sub", "rsp", "8");
+        self.mov_r2m(t, &symbol.address(), &src);
+    }
+
+    fn assign(&mut self, t: &Type, name: &str, span: Span) -> Result<(), Diagnostic> {
+        let symbol = match self.symbols.get(name) {
+            Some(symbol) => symbol.clone(),
+            None => {
+                return Err(Diagnostic::error(
+                    ErrorKind::UndeclaredVariable,
+                    span,
+                    format!("Assignment to an undeclared variable '{}'.", name),
+                ))
+            }
+        };
+        if symbol.type_ != *t {
+            return Err(Diagnostic::error(
+                ErrorKind::TypeMismatch,
+                span,
+                format!("Type mismatch in assignment to '{}'.", name),
+            ));
+        }
+        let src = self.reg_alloc.peek(t);
+        self.mov_r2m(t, &symbol.address(), &src);
+        Ok(())
+    }
+
+    fn push(&mut self, t: &Type, src: &Register) {
+        match t {
+            Type::F64 => {
+                self.asm_file.write_instruction2("sub", "rsp", "8");
+                self.asm_file
+                    .write_instruction2("movsd", "qword [rsp]", &src.to_string());
+            }
+            _ => self.asm_file.write_instruction1("push", &src.to_string()),
+        }
+        self.stack_cells.push(true);
+    }
+
+    fn pop(&mut self, t: &Type, dst: &Register) {
+        match t {
+            Type::F64 => {
+                self.asm_file
+                    .write_instruction2("movsd", &dst.to_string(), "qword [rsp]");
+                self.asm_file.write_instruction2("add", "rsp", "8");
+            }
+            _ => self.asm_file.write_instruction1("pop", &dst.to_string()),
+        }
+        self.stack_cells.pop();
+    }
+
+    /// Bytes between the last variable slot and `rsp`.
+    fn pushed(&self) -> usize {
+        self.stack_cells.len() * 8
+    }
+
+    /// Allocates a register, spilling the oldest value of the same register
+    /// class to the stack when all of them are taken.
+    fn alloc(&mut self, t: &Type) -> Register {
+        if self.reg_alloc.is_full(t) {
+            self.spill(t);
+        }
+        self.reg_alloc.alloc(t)
+    }
+
+    fn spill(&mut self, t: &Type) {
+        // Spilled values are addressed through `rbp` rather than popped, since
+        // the two register classes don't release their values in stack order.
+        let slot = self.symbols.stack_size() + self.pushed() + 8;
+        let src = self.reg_alloc.spill(t, slot);
+        self.push(t, &src);
+    }
+
+    /// Reloads spilled values until the top `count` values of `t`'s register
+    /// class are in registers, releasing stack slots that are no longer used.
+    fn ensure_resident(&mut self, t: &Type, count: usize) {
+        while self.reg_alloc.resident(t) < count {
+            let (dst, slot) = match self.reg_alloc.reload(t) {
+                Some(reload) => reload,
+                None => return,
+            };
+            let instruction = match t {
+                Type::F64 => "movsd",
+                _ => "mov",
+            };
+            self.asm_file.write_instruction2(
+                instruction,
+                &dst.to_string(),
+                &format!("qword [rbp - {}]", slot),
+            );
+
+            let cell = (slot - self.symbols.stack_size()) / 8 - 1;
+            self.stack_cells[cell] = false;
+            let used = self.stack_cells.iter().rposition(|used| *used);
+            let free = self.stack_cells.len() - used.map_or(0, |i| i + 1);
+            if free > 0 {
+                self.stack_cells.truncate(self.stack_cells.len() - free);
+                self.asm_file
+                    .write_instruction2("add", "rsp", &(free * 8).to_string());
+            }
+        }
+    }
+
+    /// Saves every register-resident value to the stack ahead of a call.
+    ///
+    /// Every register the allocator hands out is caller-saved, so live values
+    /// are spilled and reloaded lazily once they are needed again.
+    fn save_live(&mut self) {
+        for t in [Type::I32, Type::F64] {
+            while self.reg_alloc.resident(&t) > 0 {
+                self.spill(&t);
             }
         }
-        self.asm_file.write_instruction1("call", "printf");
     }
 
-    fn var(&mut self, t: &Type) {
-        let a = self.reg_alloc.peek(t);
-        self.asm_file.write_instruction1("push", &a.to_string());
+    /// Emits a `call`, padding the stack so that `rsp` is 16-byte aligned at
+    /// the call site as the System V ABI requires.
+    fn call_aligned(&mut self, label: &str) {
+        // `rbp` itself is aligned, everything below it is variables and pushes.
+        let padding = (self.symbols.stack_size() + self.pushed()) % 16;
+        if padding != 0 {
+            self.asm_file.write_instruction2("sub", "rsp", "8");
+        }
+        self.asm_file.write_instruction1("call", label);
+        if padding != 0 {
+            self.asm_file.write_instruction2("add", "rsp", "8");
+        }
     }
 
-    fn compile_expr(&mut self, expr: &Expr) -> Result<Type, Box<dyn Error>> {
+    /// Calls a user-defined function, leaving its result (if any) in a freshly
+    /// allocated register.
+    fn call(
+        &mut self,
+        return_type: Option<Type>,
+        name: &str,
+        arguments: &[Expr],
+        span: Span,
+    ) -> Result<(), Diagnostic> {
+        let label = match self.functions.get(name) {
+            Some(label) => label.clone(),
+            None => {
+                return Err(Diagnostic::error(
+                    ErrorKind::UndeclaredFunction,
+                    span,
+                    format!("Undeclared function '{}'.", name),
+                ))
+            }
+        };
+
+        self.save_live();
+
+        // Arguments are evaluated onto the stack first because the argument
+        // registers overlap with the ones handed out by the allocator.
+        let mut types = Vec::new();
+        for argument in arguments {
+            let t = self.compile_expr(argument)?;
+            let src = self.reg_alloc.dealloc(&t);
+            self.push(&t, &src);
+            types.push(t);
+        }
+
+        let mut general = ARGUMENT_REGISTERS.iter();
+        let mut simd = ARGUMENT_REGISTERS_SIMD.iter();
+        let mut targets = Vec::new();
+        for t in &types {
+            let target = match t {
+                Type::F64 => simd.next(),
+                _ => general.next(),
+            };
+            match target {
+                Some(target) => targets.push(target.clone()),
+                None => {
+                    return Err(Diagnostic::error(
+                        ErrorKind::Codegen,
+                        span,
+                        format!("Too many arguments to '{}'.", name),
+                    )
+                    .with_note("only arguments passed in registers are supported."))
+                }
+            }
+        }
+        for (t, target) in types.iter().zip(targets.iter()).rev() {
+            self.pop(t, target);
+        }
+
+        self.call_aligned(&label);
+
+        if let Some(t) = return_type {
+            let dst = self.alloc(&t);
+            match t {
+                Type::F64 => self.mov_r2r(&t, &dst, &Xmm0),
+                _ => self.mov_r2r(&t, &dst, &Rax),
+            }
+        }
+        Ok(())
+    }
+
+    fn function(
+        &mut self,
+        name: &str,
+        params: &[(String, Type)],
+        body: &[Stmt],
+        span: Span,
+    ) -> Result<(), Diagnostic> {
+        let label = format!("__aeiou__function_{}", self.functions.len());
+        self.functions.insert(name.to_string(), label.clone());
+
+        let symbols = std::mem::replace(&mut self.symbols, SymbolTable::new());
+        self.asm_file.begin_function(&label);
+
+        let mut general = ARGUMENT_REGISTERS.iter();
+        let mut simd = ARGUMENT_REGISTERS_SIMD.iter();
+        for (param, t) in params {
+            let src = match t {
+                Type::F64 => simd.next(),
+                _ => general.next(),
+            };
+            match src {
+                Some(src) => {
+                    let symbol = self.symbols.declare(param, t);
+                    self.asm_file.write_instruction2("sub", "rsp", "8");
+                    self.mov_r2m(t, &symbol.address(), src);
+                }
+                None => {
+                    return Err(Diagnostic::error(
+                        ErrorKind::Codegen,
+                        span,
+                        format!("Too many parameters in '{}'.", name),
+                    )
+                    .with_note("only parameters passed in registers are supported."))
+                }
+            }
+        }
+
+        let result = self.compile_block(body);
+        self.asm_file.write_text("leave");
+        self.asm_file.write_text("ret");
+        self.symbols = symbols;
+        result
+    }
+
+    fn compile_expr(&mut self, expr: &Expr) -> Result<Type, Diagnostic> {
         match expr {
-            Expr::Binary(t, left, op, right) => {
-                self.compile_expr(left)?;
-                self.compile_expr(right)?;
+            Expr::Binary(t, left, op, right, span) => {
+                let operand_t = self.compile_expr(left)?;
+                let right_t = self.compile_expr(right)?;
+                match operand_t == Type::F64 && right_t != Type::F64 {
+                    // `f64 ** i32` has one operand in each register class.
+                    true => {
+                        self.ensure_resident(&operand_t, 1);
+                        self.ensure_resident(&right_t, 1);
+                    }
+                    false => self.ensure_resident(&operand_t, 2),
+                }
 
-                let src = self.reg_alloc.dealloc(t);
+                if let TokenType::EqualEqual
+                | TokenType::BangEqual
+                | TokenType::Less
+                | TokenType::LessEqual
+                | TokenType::Greater
+                | TokenType::GreaterEqual = op
+                {
+                    self.compare(&operand_t, op, *span)?;
+                    return Ok(*t);
+                }
+
+                let src = self.reg_alloc.dealloc(&right_t);
                 let dst = self.reg_alloc.peek(t);
 
                 match op {
-                    TokenType::Plus => self.add(t, &dst, &src),
-                    TokenType::Minus => self.sub(t, &dst, &src),
-                    TokenType::Star => self.mul(t, &dst, &src),
-                    TokenType::Slash => self.div(t, &dst, &src),
-                    _ => return Err("Invalid operator".into()),
+                    TokenType::Plus => {
+                        self.add(t, &dst, &src);
+                        self.check_overflow(t, *span);
+                    }
+                    TokenType::Minus => {
+                        self.sub(t, &dst, &src);
+                        self.check_overflow(t, *span);
+                    }
+                    TokenType::Star => {
+                        self.mul(t, &dst, &src);
+                        self.check_overflow(t, *span);
+                    }
+                    TokenType::Slash => {
+                        self.check_division(t, &dst, &src, *span);
+                        self.div(t, &dst, &src);
+                    }
+                    TokenType::Percent => {
+                        self.check_division(t, &dst, &src, *span);
+                        self.rem(t, &dst, &src);
+                    }
+                    TokenType::StarStar => self.power(t, &dst, &src, *span),
+                    TokenType::Ampersand | TokenType::Pipe | TokenType::Caret => {
+                        self.bitwise(t, op, &dst, &src)
+                    }
+                    TokenType::LessLess | TokenType::GreaterGreater => {
+                        self.shift(t, op, &dst, &src)
+                    }
+                    _ => {
+                        return Err(Diagnostic::error(
+                            ErrorKind::Codegen,
+                            *span,
+                            "Invalid operator.",
+                        ))
+                    }
                 };
 
                 Ok(*t)
             }
-            Expr::Grouping(t, group) => {
+            Expr::Grouping(t, group, _) => {
                 self.compile_expr(group)?;
                 Ok(*t)
             }
-            Expr::Unary(t, u) => {
+            Expr::Unary(t, u, span) => {
                 self.compile_expr(u)?;
                 let dst = self.reg_alloc.peek(t);
                 self.neg(t, &dst);
+                self.check_overflow(t, *span);
+                Ok(*t)
+            }
+            Expr::Logical(t, left, op, right, _) => {
+                // Both paths must leave the allocator in the same state, so
+                // nothing may be spilled only on the path through `right`.
+                self.save_live();
+                let end = self.new_label();
+                self.compile_expr(left)?;
+                let dst = self.reg_alloc.peek(t);
+                self.asm_file
+                    .write_instruction2("test", &dst.dword(), &dst.dword());
+                match op {
+                    TokenType::And => self.asm_file.write_instruction1("jz", &end),
+                    _ => self.asm_file.write_instruction1("jnz", &end),
+                }
+                self.reg_alloc.dealloc(t);
+                self.compile_expr(right)?;
+                self.label(&end);
                 Ok(*t)
             }
-            Expr::Literal(t, lit) => {
-                let dst = self.reg_alloc.alloc(t);
+            Expr::Not(t, value, _) => {
+                self.compile_expr(value)?;
+                let dst = self.reg_alloc.peek(t);
+                match t {
+                    Type::Bool => self.asm_file.write_instruction2("xor", &dst.dword(), "1"),
+                    _ => self
+                        .asm_file
+                        .write_instruction1("not", &Self::operand(t, &dst)),
+                }
+                Ok(*t)
+            }
+            Expr::Cast(t, value, _) => {
+                let from = self.compile_expr(value)?;
+                self.cast(&from, t);
+                Ok(*t)
+            }
+            Expr::Literal(t, lit, _) => {
+                let dst = self.alloc(t);
                 let literal = self.store_literal(t, lit);
-                self.mov_m2r(t, &dst, &literal);
+                match t {
+                    Type::Str => self.mov_l2r(t, &dst, &literal),
+                    _ => self.mov_m2r(t, &dst, &literal),
+                }
+                Ok(*t)
+            }
+            Expr::Call(t, name, arguments, span) => {
+                self.call(Some(*t), name, arguments, *span)?;
                 Ok(*t)
             }
+            Expr::Variable(_t, name, span) => {
+                let symbol = match self.symbols.get(name) {
+                    Some(symbol) => symbol.clone(),
+                    None => {
+                        return Err(Diagnostic::error(
+                            ErrorKind::UndeclaredVariable,
+                            *span,
+                            format!("Undeclared variable '{}'.", name),
+                        ))
+                    }
+                };
+                let dst = self.alloc(&symbol.type_);
+                self.mov_m2r(&symbol.type_, &dst, &symbol.address());
+                Ok(symbol.type_)
+            }
         }
     }
 
-    pub fn compile(&mut self, declarations: Vec<Stmt>) -> Result<(), Box<dyn Error>> {
-        declarations
+    fn new_label(&mut self) -> String {
+        self.label_count += 1;
+        format!("__aeiou__label_{}", self.label_count)
+    }
+
+    fn label(&mut self, label: &str) {
+        self.asm_file.write_text(&format!("{}:", label));
+    }
+
+    /// Jumps out of the innermost loop, releasing the stack slots of every
+    /// scope opened inside it first.
+    fn jump_loop(&mut self, to_start: bool) {
+        let loop_ = self.loops.last().unwrap();
+        let stack_size = loop_.stack_size;
+        let label = match to_start {
+            true => loop_.start.clone(),
+            false => loop_.end.clone(),
+        };
+        if self.symbols.stack_size() != stack_size {
+            self.asm_file
+                .write_instruction2("lea", "rsp", &format!("[rbp - {}]", stack_size));
+        }
+        self.asm_file.write_instruction1("jmp", &label);
+    }
+
+    fn compile_block(&mut self, statements: &[Stmt]) -> Result<(), Diagnostic> {
+        self.symbols.begin_scope();
+        statements
             .iter()
-            .try_for_each(|stmt| -> Result<(), Box<dyn Error>> {
-                match stmt {
-                    Stmt::Print(expr) => {
-                        let t = self.compile_expr(expr)?;
-                        self.print(&t);
-                        self.reg_alloc.dealloc(&t);
-                        Ok(())
-                    }
-                    Stmt::Expression(expr) => {
-                        let t = self.compile_expr(expr)?;
-                        self.reg_alloc.dealloc(&t);
-                        Ok(())
-                    }
-                    Stmt::Var(type_, name, expr) => {
-                        let t = self.compile_expr(expr)?;
-                        self.var(&t);
-                        self.reg_alloc.dealloc(&t);
-                        Ok(())
+            .try_for_each(|stmt| self.compile_stmt(stmt))?;
+        let size = self.symbols.end_scope();
+        if size > 0 {
+            self.asm_file
+                .write_instruction2("add", "rsp", &size.to_string());
+        }
+        Ok(())
+    }
+
+    fn compile_stmt(&mut self, stmt: &Stmt) -> Result<(), Diagnostic> {
+        match stmt {
+            Stmt::Print(expr, _) => {
+                let t = self.compile_expr(expr)?;
+                self.print(&t);
+                Ok(())
+            }
+            Stmt::Expression(expr, _) => {
+                let t = self.compile_expr(expr)?;
+                self.reg_alloc.dealloc(&t);
+                Ok(())
+            }
+            Stmt::Var(_type, name, expr, _) => {
+                let t = self.compile_expr(expr)?;
+                self.var(&t, name);
+                self.reg_alloc.dealloc(&t);
+                Ok(())
+            }
+            Stmt::Assign(_type, name, expr, span) => {
+                let t = self.compile_expr(expr)?;
+                self.assign(&t, name, *span)?;
+                self.reg_alloc.dealloc(&t);
+                Ok(())
+            }
+            Stmt::If(condition, then_branch, else_branch, _) => {
+                let else_label = self.new_label();
+                let end_label = self.new_label();
+
+                let t = self.compile_expr(condition)?;
+                let cond = self.reg_alloc.dealloc(&t);
+                self.asm_file
+                    .write_instruction2("test", &cond.dword(), &cond.dword());
+                self.asm_file.write_instruction1("jz", &else_label);
+
+                self.compile_block(then_branch)?;
+                self.asm_file.write_instruction1("jmp", &end_label);
+                self.label(&else_label);
+                if let Some(else_branch) = else_branch {
+                    self.compile_block(else_branch)?;
+                }
+                self.label(&end_label);
+                Ok(())
+            }
+            Stmt::While(condition, body, _) => {
+                let start_label = self.new_label();
+                let end_label = self.new_label();
+
+                self.label(&start_label);
+                let t = self.compile_expr(condition)?;
+                let cond = self.reg_alloc.dealloc(&t);
+                self.asm_file
+                    .write_instruction2("test", &cond.dword(), &cond.dword());
+                self.asm_file.write_instruction1("jz", &end_label);
+
+                self.loops.push(Loop {
+                    start: start_label.clone(),
+                    end: end_label.clone(),
+                    stack_size: self.symbols.stack_size(),
+                });
+                let result = self.compile_block(body);
+                self.loops.pop();
+                result?;
+
+                self.asm_file.write_instruction1("jmp", &start_label);
+                self.label(&end_label);
+                Ok(())
+            }
+            Stmt::Function(..) => Ok(()),
+            Stmt::Return(value, _) => {
+                if let Some(value) = value {
+                    let t = self.compile_expr(value)?;
+                    let src = self.reg_alloc.dealloc(&t);
+                    match t {
+                        Type::F64 => self.mov_r2r(&t, &Xmm0, &src),
+                        _ => self.mov_r2r(&t, &Rax, &src),
                     }
                 }
-            })?;
-        fs::write("./target/program.asm", self.asm_file.finalize())?;
-        Ok(())
+                self.asm_file.write_text("leave");
+                self.asm_file.write_text("ret");
+                Ok(())
+            }
+            Stmt::Call(name, arguments, span) => self.call(None, name, arguments, *span),
+            Stmt::Break(_) => {
+                self.jump_loop(false);
+                Ok(())
+            }
+            Stmt::Continue(_) => {
+                self.jump_loop(true);
+                Ok(())
+            }
+        }
+    }
+
+    /// Compiles a parsed program to NASM assembly for x86-64 Linux.
+    pub fn compile(&mut self, declarations: Vec<Stmt>) -> Result<String, Diagnostic> {
+        for stmt in &declarations {
+            if let Stmt::Function(name, params, _, body, span) = stmt {
+                self.function(name, params, body, *span)?;
+            }
+        }
+
+        self.asm_file.begin_function("main");
+        declarations
+            .iter()
+            .try_for_each(|stmt| self.compile_stmt(stmt))?;
+        self.mov_l2r(&Type::I32, &Rdi, "0");
+        self.call_aligned("exit");
+        if self.checked {
+            self.runtime_errors();
+        }
+        Ok(self.asm_file.finalize())
     }
 }
@@ -1,7 +1,10 @@
-use crate::compiler::register::Register::{Rcx, Rdi, Rsi, Xmm1, Xmm2, Xmm3, Xmm4, Xmm5, Xmm6, Xmm7, R10, R11, R8, R9};
+use crate::compiler::register::Register::{
+    Rcx, Rdi, Rsi, Xmm1, Xmm2, Xmm3, Xmm4, Xmm5, Xmm6, Xmm7, R10, R11, R8, R9,
+};
 use crate::parser::Type;
 use std::fmt::{Display, Formatter};
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Register {
     Rax,
@@ -37,9 +40,80 @@ impl Display for Register {
     }
 }
 
+impl Register {
+    pub fn byte(&self) -> String {
+        match self {
+            Register::Rax => "al".to_string(),
+            Register::Rcx => "cl".to_string(),
+            Register::Rdx => "dl".to_string(),
+            Register::Rbx => "bl".to_string(),
+            Register::Rsi => "sil".to_string(),
+            Register::Rdi => "dil".to_string(),
+            Register::Rsp => "spl".to_string(),
+            Register::Rbp => "bpl".to_string(),
+            Register::Xmm0
+            | Register::Xmm1
+            | Register::Xmm2
+            | Register::Xmm3
+            | Register::Xmm4
+            | Register::Xmm5
+            | Register::Xmm6
+            | Register::Xmm7 => panic!("{} has no byte register.", self),
+            _ => format!("{}b", self),
+        }
+    }
+
+    pub fn dword(&self) -> String {
+        match self {
+            Register::Rax
+            | Register::Rcx
+            | Register::Rdx
+            | Register::Rbx
+            | Register::Rsi
+            | Register::Rdi
+            | Register::Rsp
+            | Register::Rbp => format!("e{}", &self.to_string()[1..]),
+            Register::Xmm0
+            | Register::Xmm1
+            | Register::Xmm2
+            | Register::Xmm3
+            | Register::Xmm4
+            | Register::Xmm5
+            | Register::Xmm6
+            | Register::Xmm7 => panic!("{} has no dword register.", self),
+            _ => format!("{}d", self),
+        }
+    }
+}
+
+pub const ARGUMENT_REGISTERS: [Register; 6] = [
+    Register::Rdi,
+    Register::Rsi,
+    Register::Rdx,
+    Register::Rcx,
+    Register::R8,
+    Register::R9,
+];
+
+pub const ARGUMENT_REGISTERS_SIMD: [Register; 8] = [
+    Register::Xmm0,
+    Register::Xmm1,
+    Register::Xmm2,
+    Register::Xmm3,
+    Register::Xmm4,
+    Register::Xmm5,
+    Register::Xmm6,
+    Register::Xmm7,
+];
+
+/// Values allocated from a `RegisterStack` form a stack where value `i` lives
+/// in `stack[i % stack.len()]`. Only the newest `resident` values are held in
+/// registers; older ones have been spilled to the stack slots in `spilled`.
 struct RegisterStack {
     stack: Vec<Register>,
     stack_ptr: usize,
+    resident: usize,
+    spilled: Vec<usize>,
 }
 
 impl RegisterStack {
@@ -47,21 +121,44 @@ impl RegisterStack {
         RegisterStack {
             stack: registers,
             stack_ptr: 0,
+            resident: 0,
+            spilled: Vec::new(),
         }
     }
 
+    fn register(&self, value: usize) -> Register {
+        self.stack[value % self.stack.len()].clone()
+    }
+
     pub fn peek(&mut self) -> Register {
-        self.stack[self.stack_ptr - 1].clone()
+        self.register(self.stack_ptr - 1)
     }
 
     pub fn dealloc(&mut self) -> Register {
         self.stack_ptr -= 1;
-        self.stack[self.stack_ptr].clone()
+        self.resident -= 1;
+        self.register(self.stack_ptr)
     }
 
     pub fn alloc(&mut self) -> Register {
         self.stack_ptr += 1;
-        self.stack[self.stack_ptr - 1].clone()
+        self.resident += 1;
+        self.register(self.stack_ptr - 1)
+    }
+
+    pub fn oldest(&self) -> Register {
+        self.register(self.stack_ptr - self.resident)
+    }
+
+    pub fn spill(&mut self, slot: usize) {
+        self.resident -= 1;
+        self.spilled.push(slot);
+    }
+
+    pub fn reload(&mut self) -> Option<(Register, usize)> {
+        let slot = self.spilled.pop()?;
+        self.resident += 1;
+        Some((self.register(self.stack_ptr - self.resident), slot))
     }
 }
 
@@ -80,7 +177,7 @@ impl RegisterAllocator {
 
     fn get_stack(&mut self, t: &Type) -> &mut RegisterStack {
         match t {
-            Type::I32 => &mut self.stack,
+            Type::I32 | Type::I64 | Type::Bool | Type::Str => &mut self.stack,
             Type::F64 => &mut self.stack_simd,
         }
     }
@@ -93,9 +190,33 @@ impl RegisterAllocator {
         self.get_stack(t).dealloc()
     }
 
-    pub fn alloc(&mut self, t: &Type) -> Register {
+    /// Number of values of `t`'s register class currently held in registers.
+    pub fn resident(&mut self, t: &Type) -> usize {
+        self.get_stack(t).resident
+    }
+
+    pub fn is_full(&mut self, t: &Type) -> bool {
+        let s = self.get_stack(t);
+        s.resident >= s.stack.len()
+    }
+
+    /// Marks the oldest register-resident value as stored in the stack slot at
+    /// `rbp - slot`, freeing its register, and returns that register.
+    pub fn spill(&mut self, t: &Type, slot: usize) -> Register {
         let s = self.get_stack(t);
-        if s.stack_ptr >= s.stack.len() {
+        let register = s.oldest();
+        s.spill(slot);
+        register
+    }
+
+    /// Brings the most recently spilled value back, returning the register it
+    /// belongs in and the slot it was stored in.
+    pub fn reload(&mut self, t: &Type) -> Option<(Register, usize)> {
+        self.get_stack(t).reload()
+    }
+
+    pub fn alloc(&mut self, t: &Type) -> Register {
+        if self.is_full(t) {
             panic!("Out of registers!")
         }
         self.get_stack(t).alloc()
@@ -0,0 +1,62 @@
+use crate::parser::Type;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub type_: Type,
+    pub offset: usize,
+}
+
+impl Symbol {
+    pub fn address(&self) -> String {
+        format!("rbp - {}", self.offset)
+    }
+}
+
+pub struct SymbolTable {
+    scopes: Vec<HashMap<String, Symbol>>,
+    stack_size: usize,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable {
+            scopes: vec![HashMap::new()],
+            stack_size: 0,
+        }
+    }
+
+    pub fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// Drops the innermost scope and returns the number of stack bytes its
+    /// variables occupied, so the caller can release them.
+    pub fn end_scope(&mut self) -> usize {
+        let scope = self.scopes.pop().unwrap();
+        let size = scope.len() * 8;
+        self.stack_size -= size;
+        size
+    }
+
+    pub fn declare(&mut self, name: &str, t: &Type) -> Symbol {
+        self.stack_size += 8;
+        let symbol = Symbol {
+            type_: *t,
+            offset: self.stack_size,
+        };
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), symbol.clone());
+        symbol
+    }
+
+    pub fn stack_size(&self) -> usize {
+        self.stack_size
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+}
@@ -0,0 +1,114 @@
+use crate::lexer::Span;
+use std::fmt::{Display, Formatter};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A character the lexer does not recognise.
+    InvalidCharacter,
+    /// A numeric literal that is malformed, has an unknown suffix or does not
+    /// fit its type, which is `i32` or `f64` unless a suffix gives another.
+    InvalidNumber,
+    /// A string literal that is not closed before the end of its line.
+    UnterminatedString,
+    /// A backslash in a string literal followed by an unknown character.
+    InvalidEscape,
+    /// A `/*` comment without a closing `*/`.
+    UnterminatedComment,
+    /// The parser found a token other than the one the grammar requires.
+    UnexpectedToken,
+    TypeMismatch,
+    UndeclaredVariable,
+    UndeclaredFunction,
+    AlreadyDeclared,
+    UnknownType,
+    WrongArgumentCount,
+    /// A function with more parameters of one register class than there are
+    /// registers to pass them in.
+    TooManyParameters,
+    /// A statement used outside of the construct it belongs to, such as
+    /// `შეწყვიტე` outside of a loop.
+    MisplacedStatement,
+    MissingReturn,
+    /// Code generation could not lower a construct to assembly.
+    Codegen,
+    /// A runtime check failed while interpreting a program.
+    Runtime,
+    /// The command line could not be understood.
+    Usage,
+    Io,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub kind: ErrorKind,
+    pub severity: Severity,
+    pub span: Option<Span>,
+    pub message: String,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(kind: ErrorKind, span: Span, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            kind,
+            severity: Severity::Error,
+            span: Some(span),
+            message: message.into(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    /// An error that is not tied to a location in the source.
+    pub fn global(kind: ErrorKind, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            kind,
+            severity: Severity::Error,
+            span: None,
+            message: message.into(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Diagnostic {
+        self.help = Some(help.into());
+        self
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.span {
+            Some(span) => write!(
+                f,
+                "{}:{}: {}: {}",
+                span.line, span.column, self.severity, self.message
+            ),
+            None => write!(f, "{}: {}", self.severity, self.message),
+        }
+    }
+}
+
+impl std::error::Error for Diagnostic {}
@@ -0,0 +1,499 @@
+mod value;
+
+pub use crate::interpreter::value::Value;
+
+use crate::diagnostic::{Diagnostic, ErrorKind};
+use crate::lexer::{Span, TokenType};
+use crate::parser::{Expr, Stmt, Type};
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+use std::thread;
+
+const OVERFLOW: &str = "არითმეტიკული გადავსება";
+const DIVISION_BY_ZERO: &str = "ნულზე გაყოფა";
+const STACK_OVERFLOW: &str = "სტეკის გადავსება";
+
+/// The deepest nesting of calls a program may reach. Compiled code manages
+/// far more within an 8 MiB stack, but the interpreter needs several KiB of
+/// its own stack per call, so programs recursing past this only run natively.
+const MAX_CALL_DEPTH: usize = 4_000;
+
+/// Stack a call may take, with room for a function body nested a few
+/// levels deep in an unoptimised build.
+const FRAME_SIZE: usize = 24 << 10;
+
+/// Stack for the thread programs are evaluated on, about 94 MiB.
+const STACK_SIZE: usize = MAX_CALL_DEPTH * FRAME_SIZE;
+
+struct Function {
+    params: Vec<String>,
+    body: Vec<Stmt>,
+}
+
+/// Errors are boxed while evaluating, since every level of a program's
+/// recursion holds a few results on the interpreter's stack.
+type Evaluation<T> = Result<T, Box<Diagnostic>>;
+
+/// How a statement hands control back to the block that contains it.
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Option<Value>),
+}
+
+/// Evaluates a parsed program directly, writing what it prints to `out`.
+///
+/// Results match the compiled program: integers wrap at their width, floats
+/// follow the same instruction sequences and print like `printf("%f")`.
+/// Where the compiled program would crash, the interpreter reports a runtime
+/// error instead: for a division the hardware traps on, by zero or of the
+/// minimum value by -1. Calls nested deeper than `MAX_CALL_DEPTH` are also a
+/// runtime error, long before the compiled program would run out of stack.
+pub struct Interpreter<W: Write> {
+    out: W,
+    checked: bool,
+    functions: HashMap<String, Arc<Function>>,
+    scopes: Vec<HashMap<String, Value>>,
+    depth: usize,
+}
+
+impl<W: Write + Send> Interpreter<W> {
+    pub fn new(out: W) -> Interpreter<W> {
+        Interpreter {
+            out,
+            checked: false,
+            functions: HashMap::new(),
+            scopes: vec![HashMap::new()],
+            depth: 0,
+        }
+    }
+
+    /// Enables runtime checks for integer overflow and division by zero.
+    pub fn checked(mut self, checked: bool) -> Interpreter<W> {
+        self.checked = checked;
+        self
+    }
+
+    /// A failed runtime check at `span`, reported with `ErrorKind::Runtime`.
+    fn runtime_error(span: Span, message: &str) -> Box<Diagnostic> {
+        Box::new(Diagnostic::error(ErrorKind::Runtime, span, message))
+    }
+
+    /// An operator the parser's type checks should have ruled out.
+    fn invalid_operator(span: Span) -> Box<Diagnostic> {
+        Box::new(Diagnostic::error(
+            ErrorKind::TypeMismatch,
+            span,
+            "Invalid operator.",
+        ))
+    }
+
+    /// Wraps `n` to the width of `t`, or fails in checked mode if it doesn't
+    /// fit.
+    fn wrap(&self, t: &Type, n: i128, span: Span) -> Evaluation<Value> {
+        let value = Value::integer(t, n);
+        if self.checked && value.as_integer() != Some(n) {
+            return Err(Self::runtime_error(span, OVERFLOW));
+        }
+        Ok(value)
+    }
+
+    /// `a / b` or `a % b` on integers, which `idiv` refuses for a zero divisor
+    /// and for a quotient that doesn't fit.
+    fn divide(&self, t: &Type, op: &TokenType, a: i128, b: i128, span: Span) -> Evaluation<Value> {
+        if b == 0 {
+            return Err(Self::runtime_error(span, DIVISION_BY_ZERO));
+        }
+        if Value::integer(t, a / b).as_integer() != Some(a / b) {
+            return Err(Self::runtime_error(span, OVERFLOW));
+        }
+        match op {
+            TokenType::Slash => Ok(Value::integer(t, a / b)),
+            _ => Ok(Value::integer(t, a % b)),
+        }
+    }
+
+    /// Raises an integer to the power `exponent` by repeated squaring, with
+    /// the same multiplications, and so the same overflows, as the compiled
+    /// code.
+    fn power(&self, t: &Type, base: i128, exponent: i32, span: Span) -> Evaluation<Value> {
+        let mut base = base;
+        let mut result = 1;
+        let mut counter = exponent.unsigned_abs();
+        while counter != 0 {
+            if counter & 1 == 1 {
+                result = self.wrap(t, result * base, span)?.as_integer().unwrap();
+            }
+            counter >>= 1;
+            if counter != 0 {
+                base = self.wrap(t, base * base, span)?.as_integer().unwrap();
+            }
+        }
+        match exponent < 0 {
+            true => self.divide(t, &TokenType::Slash, 1, result, span),
+            false => Ok(Value::integer(t, result)),
+        }
+    }
+
+    fn power_f64(base: f64, exponent: i32) -> f64 {
+        let mut base = base;
+        let mut result = 1.0;
+        let mut counter = exponent.unsigned_abs();
+        while counter != 0 {
+            if counter & 1 == 1 {
+                result *= base;
+            }
+            counter >>= 1;
+            if counter != 0 {
+                base *= base;
+            }
+        }
+        match exponent < 0 {
+            true => 1.0 / result,
+            false => result,
+        }
+    }
+
+    fn compare(op: &TokenType, left: &Value, right: &Value) -> bool {
+        match (left.as_f64(), right.as_f64()) {
+            (Some(a), Some(b)) => match op {
+                TokenType::EqualEqual => a == b,
+                TokenType::BangEqual => a != b,
+                TokenType::Less => a < b,
+                TokenType::LessEqual => a <= b,
+                TokenType::Greater => a > b,
+                _ => a >= b,
+            },
+            _ => {
+                let (a, b) = (left.as_integer(), right.as_integer());
+                match op {
+                    TokenType::EqualEqual => a == b,
+                    TokenType::BangEqual => a != b,
+                    TokenType::Less => a < b,
+                    TokenType::LessEqual => a <= b,
+                    TokenType::Greater => a > b,
+                    _ => a >= b,
+                }
+            }
+        }
+    }
+
+    fn binary(
+        &self,
+        t: &Type,
+        op: &TokenType,
+        left: Value,
+        right: Value,
+        span: Span,
+    ) -> Evaluation<Value> {
+        if let TokenType::EqualEqual
+        | TokenType::BangEqual
+        | TokenType::Less
+        | TokenType::LessEqual
+        | TokenType::Greater
+        | TokenType::GreaterEqual = op
+        {
+            return Ok(Value::Bool(Self::compare(op, &left, &right)));
+        }
+
+        if let Some(a) = left.as_f64() {
+            let value = match (op, right) {
+                (TokenType::Plus, Value::F64(b)) => a + b,
+                (TokenType::Minus, Value::F64(b)) => a - b,
+                (TokenType::Star, Value::F64(b)) => a * b,
+                (TokenType::Slash, Value::F64(b)) => a / b,
+                // `a - trunc(a / b) * b`, as the compiled code computes it.
+                (TokenType::Percent, Value::F64(b)) => a - (a / b).trunc() * b,
+                (TokenType::StarStar, Value::I32(b)) => Self::power_f64(a, b),
+                _ => return Err(Self::invalid_operator(span)),
+            };
+            return Ok(Value::F64(value));
+        }
+
+        let (a, b) = match (left.as_integer(), right.as_integer()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Err(Self::invalid_operator(span)),
+        };
+        let bits = match t {
+            Type::I64 => 64,
+            _ => 32,
+        };
+        match op {
+            TokenType::Plus => self.wrap(t, a + b, span),
+            TokenType::Minus => self.wrap(t, a - b, span),
+            TokenType::Star => self.wrap(t, a * b, span),
+            TokenType::Slash | TokenType::Percent => self.divide(t, op, a, b, span),
+            TokenType::StarStar => self.power(t, a, b as i32, span),
+            TokenType::Ampersand => Ok(Value::integer(t, a & b)),
+            TokenType::Pipe => Ok(Value::integer(t, a | b)),
+            TokenType::Caret => Ok(Value::integer(t, a ^ b)),
+            // Like `shl` and `sar`, only the low bits of the count are used.
+            TokenType::LessLess => Ok(Value::integer(t, a << (b & (bits - 1)))),
+            TokenType::GreaterGreater => Ok(Value::integer(t, a >> (b & (bits - 1)))),
+            _ => Err(Self::invalid_operator(span)),
+        }
+    }
+
+    fn function(&self, name: &str, span: Span) -> Evaluation<Arc<Function>> {
+        match self.functions.get(name) {
+            Some(function) => Ok(function.clone()),
+            None => Err(Box::new(Diagnostic::error(
+                ErrorKind::UndeclaredFunction,
+                span,
+                format!("Undeclared function '{}'.", name),
+            ))),
+        }
+    }
+
+    fn arguments(
+        &mut self,
+        params: &[String],
+        arguments: &[Expr],
+    ) -> Evaluation<HashMap<String, Value>> {
+        let mut frame = HashMap::new();
+        for (param, argument) in params.iter().zip(arguments) {
+            let value = self.eval(argument)?;
+            frame.insert(param.clone(), value);
+        }
+        Ok(frame)
+    }
+
+    // The evaluation of a program's recursion nests `call`, `eval` and `stmt`,
+    // so their arms are kept in separate methods to keep those frames small.
+    fn call(&mut self, name: &str, arguments: &[Expr], span: Span) -> Evaluation<Option<Value>> {
+        let function = self.function(name, span)?;
+        if self.depth == MAX_CALL_DEPTH {
+            return Err(Self::runtime_error(span, STACK_OVERFLOW));
+        }
+        let frame = self.arguments(&function.params, arguments)?;
+
+        // Functions only see their own variables.
+        let scopes = std::mem::replace(&mut self.scopes, vec![frame]);
+        self.depth += 1;
+        let result = self.block(&function.body);
+        self.depth -= 1;
+        self.scopes = scopes;
+        match result? {
+            Flow::Return(value) => Ok(value),
+            _ => Ok(None),
+        }
+    }
+
+    fn variable(&mut self, name: &str, span: Span) -> Evaluation<&mut Value> {
+        match self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+        {
+            Some(value) => Ok(value),
+            None => Err(Box::new(Diagnostic::error(
+                ErrorKind::UndeclaredVariable,
+                span,
+                format!("Undeclared variable '{}'.", name),
+            ))),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Evaluation<Value> {
+        match expr {
+            Expr::Binary(t, left, op, right, span) => self.eval_binary(t, left, op, right, *span),
+            Expr::Grouping(_, group, _) => self.eval(group),
+            Expr::Unary(t, value, span) => self.negate(t, value, *span),
+            Expr::Logical(_, left, op, right, _) => self.logical(left, op, right),
+            Expr::Not(t, value, _) => self.not(t, value),
+            Expr::Cast(t, value, _) => self.cast(t, value),
+            Expr::Literal(t, literal, _) => Ok(Value::literal(t, literal)),
+            Expr::Call(_, name, arguments, span) => self.call_value(name, arguments, *span),
+            Expr::Variable(_, name, span) => self.variable(name, *span).cloned(),
+        }
+    }
+
+    fn eval_binary(
+        &mut self,
+        t: &Type,
+        left: &Expr,
+        op: &TokenType,
+        right: &Expr,
+        span: Span,
+    ) -> Evaluation<Value> {
+        let left = self.eval(left)?;
+        let right = self.eval(right)?;
+        self.binary(t, op, left, right, span)
+    }
+
+    fn negate(&mut self, t: &Type, value: &Expr, span: Span) -> Evaluation<Value> {
+        match self.eval(value)? {
+            // The compiled code multiplies by -1.0, which leaves NaN alone.
+            Value::F64(x) if x.is_nan() => Ok(Value::F64(x)),
+            Value::F64(x) => Ok(Value::F64(-x)),
+            value => self.wrap(t, -value.as_integer().unwrap_or_default(), span),
+        }
+    }
+
+    fn logical(&mut self, left: &Expr, op: &TokenType, right: &Expr) -> Evaluation<Value> {
+        let left = self.eval(left)?;
+        match (op, left.is_true()) {
+            (TokenType::And, false) | (TokenType::Or, true) => Ok(left),
+            _ => self.eval(right),
+        }
+    }
+
+    fn not(&mut self, t: &Type, value: &Expr) -> Evaluation<Value> {
+        match self.eval(value)? {
+            Value::Bool(b) => Ok(Value::Bool(!b)),
+            value => Ok(Value::integer(t, !value.as_integer().unwrap_or_default())),
+        }
+    }
+
+    fn cast(&mut self, t: &Type, value: &Expr) -> Evaluation<Value> {
+        Ok(self.eval(value)?.cast(t))
+    }
+
+    fn call_value(&mut self, name: &str, arguments: &[Expr], span: Span) -> Evaluation<Value> {
+        match self.call(name, arguments, span)? {
+            Some(value) => Ok(value),
+            None => Err(Box::new(Diagnostic::error(
+                ErrorKind::TypeMismatch,
+                span,
+                format!("'{}' did not return a value.", name),
+            ))),
+        }
+    }
+
+    fn block(&mut self, statements: &[Stmt]) -> Evaluation<Flow> {
+        self.scopes.push(HashMap::new());
+        let result = self.statements(statements);
+        self.scopes.pop();
+        result
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) -> Evaluation<Flow> {
+        for stmt in statements {
+            match self.stmt(stmt)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Evaluation<Flow> {
+        match stmt {
+            Stmt::Print(expr, _) => self.print(expr),
+            Stmt::Expression(expr, _) => {
+                self.eval(expr)?;
+                Ok(Flow::Next)
+            }
+            Stmt::Var(_, name, expr, _) => {
+                let value = self.eval(expr)?;
+                self.scopes.last_mut().unwrap().insert(name.clone(), value);
+                Ok(Flow::Next)
+            }
+            Stmt::Assign(_, name, expr, span) => self.assign(name, expr, *span),
+            Stmt::If(condition, then_branch, else_branch, _) => {
+                self.if_else(condition, then_branch, else_branch.as_deref())
+            }
+            Stmt::While(condition, body, _) => self.while_loop(condition, body),
+            Stmt::Function(..) => Ok(Flow::Next),
+            Stmt::Return(value, _) => match value {
+                Some(value) => Ok(Flow::Return(Some(self.eval(value)?))),
+                None => Ok(Flow::Return(None)),
+            },
+            Stmt::Call(name, arguments, span) => {
+                self.call(name, arguments, *span)?;
+                Ok(Flow::Next)
+            }
+            Stmt::Break(_) => Ok(Flow::Break),
+            Stmt::Continue(_) => Ok(Flow::Continue),
+        }
+    }
+
+    fn print(&mut self, expr: &Expr) -> Evaluation<Flow> {
+        let value = self.eval(expr)?;
+        writeln!(self.out, "{}", value).map_err(|err| {
+            Box::new(Diagnostic::global(
+                ErrorKind::Io,
+                format!("Could not print: {}.", err),
+            ))
+        })?;
+        Ok(Flow::Next)
+    }
+
+    fn assign(&mut self, name: &str, expr: &Expr, span: Span) -> Evaluation<Flow> {
+        let value = self.eval(expr)?;
+        *self.variable(name, span)? = value;
+        Ok(Flow::Next)
+    }
+
+    fn if_else(
+        &mut self,
+        condition: &Expr,
+        then_branch: &[Stmt],
+        else_branch: Option<&[Stmt]>,
+    ) -> Evaluation<Flow> {
+        match (self.eval(condition)?.is_true(), else_branch) {
+            (true, _) => self.block(then_branch),
+            (false, Some(else_branch)) => self.block(else_branch),
+            (false, None) => Ok(Flow::Next),
+        }
+    }
+
+    fn while_loop(&mut self, condition: &Expr, body: &[Stmt]) -> Evaluation<Flow> {
+        while self.eval(condition)?.is_true() {
+            match self.block(body)? {
+                Flow::Break => break,
+                Flow::Next | Flow::Continue => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Runs a parsed program on a thread with a stack deep enough for
+    /// `MAX_CALL_DEPTH` calls. Output is flushed before returning, so it comes
+    /// before any runtime error the caller goes on to report.
+    pub fn run(&mut self, declarations: Vec<Stmt>) -> Result<(), Diagnostic> {
+        thread::scope(|scope| {
+            let evaluation = thread::Builder::new()
+                .stack_size(STACK_SIZE)
+                .spawn_scoped(scope, || self.evaluate(declarations).map_err(|err| *err))
+                .map_err(|err| {
+                    Diagnostic::global(
+                        ErrorKind::Io,
+                        format!("Could not start the interpreter: {}.", err),
+                    )
+                })?;
+            match evaluation.join() {
+                Ok(result) => result,
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        })
+    }
+
+    fn evaluate(&mut self, declarations: Vec<Stmt>) -> Evaluation<()> {
+        let mut main = Vec::new();
+        for stmt in declarations {
+            match stmt {
+                Stmt::Function(name, params, _, body, _) => {
+                    let params = params.into_iter().map(|(name, _)| name).collect();
+                    self.functions
+                        .insert(name, Arc::new(Function { params, body }));
+                }
+                stmt => main.push(stmt),
+            }
+        }
+
+        let result = self.statements(&main);
+        let flushed = self.out.flush().map_err(|err| {
+            Box::new(Diagnostic::global(
+                ErrorKind::Io,
+                format!("Could not print: {}.", err),
+            ))
+        });
+        result?;
+        flushed
+    }
+}
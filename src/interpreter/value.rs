@@ -0,0 +1,105 @@
+use crate::parser::Type;
+use std::fmt::{Display, Formatter};
+
+/// A value computed by the interpreter. Integers are kept at their width, so
+/// arithmetic on them wraps exactly like the generated code.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    I32(i32),
+    I64(i64),
+    F64(f64),
+    Bool(bool),
+    Str(String),
+}
+
+impl Value {
+    /// The value of a literal as the parser stores it.
+    pub fn literal(t: &Type, literal: &str) -> Value {
+        match t {
+            Type::I32 => Value::I32(literal.parse().expect("checked by the lexer")),
+            Type::I64 => Value::I64(literal.parse().expect("checked by the lexer")),
+            Type::F64 => Value::F64(literal.parse().expect("checked by the lexer")),
+            Type::Bool => Value::Bool(literal == "true"),
+            Type::Str => Value::Str(literal.to_string()),
+        }
+    }
+
+    /// `n` truncated to the width of the integer type `t`.
+    pub fn integer(t: &Type, n: i128) -> Value {
+        match t {
+            Type::I64 => Value::I64(n as i64),
+            _ => Value::I32(n as i32),
+        }
+    }
+
+    /// The value as an integer, with booleans as 0 and 1 like in registers.
+    pub fn as_integer(&self) -> Option<i128> {
+        match self {
+            Value::I32(n) => Some(*n as i128),
+            Value::I64(n) => Some(*n as i128),
+            Value::Bool(b) => Some(*b as i128),
+            Value::F64(_) | Value::Str(_) => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::F64(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn is_true(&self) -> bool {
+        matches!(self, Value::Bool(true))
+    }
+
+    /// Converts the value for `როგორც`. Floats are truncated towards zero, and
+    /// ones that don't fit the target, as well as NaN, become its minimum
+    /// value, which is what `cvttsd2si` produces.
+    pub fn cast(&self, to: &Type) -> Value {
+        match (self, to) {
+            (Value::F64(x), Type::I32) => match *x >= -2147483648.0 && *x < 2147483648.0 {
+                true => Value::I32(*x as i32),
+                false => Value::I32(i32::MIN),
+            },
+            (Value::F64(x), Type::I64) => {
+                match *x >= -9223372036854775808.0 && *x < 9223372036854775808.0 {
+                    true => Value::I64(*x as i64),
+                    false => Value::I64(i64::MIN),
+                }
+            }
+            (Value::F64(_), _) => self.clone(),
+            (_, Type::F64) => match self.as_integer() {
+                Some(n) => Value::F64(n as f64),
+                None => self.clone(),
+            },
+            (_, Type::I32 | Type::I64) => match self.as_integer() {
+                Some(n) => Value::integer(to, n),
+                None => self.clone(),
+            },
+            _ => self.clone(),
+        }
+    }
+}
+
+/// Formats the value the way the compiled program's `printf` call does.
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::I32(n) => write!(f, "{}", n),
+            Value::I64(n) => write!(f, "{}", n),
+            Value::F64(x) if x.is_nan() => match x.is_sign_negative() {
+                true => write!(f, "-nan"),
+                false => write!(f, "nan"),
+            },
+            Value::F64(x) if x.is_infinite() => match x.is_sign_negative() {
+                true => write!(f, "-inf"),
+                false => write!(f, "inf"),
+            },
+            Value::F64(x) => write!(f, "{:.6}", x),
+            Value::Bool(true) => write!(f, "ჭეშმარიტი"),
+            Value::Bool(false) => write!(f, "მცდარი"),
+            Value::Str(s) => write!(f, "{}", s),
+        }
+    }
+}
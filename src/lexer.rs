@@ -1,83 +1,337 @@
+use crate::diagnostic::{Diagnostic, ErrorKind};
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum TokenType {
     Plus,
     Minus,
     Star,
+    StarStar,
     Slash,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
     LeftParen,
     RightParen,
+    LeftBrace,
+    RightBrace,
+    Comma,
     Number,
+    String,
     Print,
     Var,
+    If,
+    Else,
+    While,
+    Break,
+    Continue,
+    Function,
+    Return,
+    As,
+    And,
+    Or,
+    Not,
     Colon,
     Equal,
     EqualEqual,
+    BangEqual,
+    Less,
+    LessEqual,
+    LessLess,
+    Greater,
+    GreaterEqual,
+    GreaterGreater,
     Identifier,
     Newline,
 }
 
+/// Location of a token in the source: a byte range plus the 1-based line and
+/// column (in characters) where it starts.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Span {
+    pub offset: usize,
+    pub length: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A comment skipped by the lexer, with its delimiters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+}
+
 #[derive(Clone, Debug)]
 pub struct Token {
     pub type_: TokenType,
     pub string: String,
+    pub span: Span,
+    /// Comments between the previous token and this one.
+    pub trivia: Vec<Comment>,
 }
 
 impl Token {
     pub fn new(type_: TokenType, string: String) -> Token {
-        Token { type_, string }
+        Token {
+            type_,
+            string,
+            span: Span::default(),
+            trivia: Vec::new(),
+        }
     }
     pub fn from_type(type_: TokenType) -> Token {
         Token {
             type_,
             string: String::new(),
+            span: Span::default(),
+            trivia: Vec::new(),
         }
     }
 }
 
-pub fn tokenize(input: &str) -> Result<Vec<Token>, &str> {
+/// Splits a numeric literal into its digits and its type suffix, such as
+/// `("10", "i64")` for `10i64`. The suffix is empty when there is none.
+pub fn split_number(number: &str) -> (&str, &str) {
+    let at = number
+        .find(|c: char| c.is_ascii_lowercase())
+        .unwrap_or(number.len());
+    number.split_at(at)
+}
+
+fn escape(c: char) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        'r' => Some('\r'),
+        '"' => Some('"'),
+        '\\' => Some('\\'),
+        _ => None,
+    }
+}
+
+fn invalid_escape(c: char, offset: usize, line: usize, column: usize) -> Diagnostic {
+    Diagnostic::error(
+        ErrorKind::InvalidEscape,
+        Span {
+            offset,
+            length: 1 + c.len_utf8(),
+            line,
+            column,
+        },
+        format!("unknown escape sequence '\\{}'.", c),
+    )
+    .with_help("valid escapes are '\\n', '\\t', '\\r', '\\\"' and '\\\\'.")
+}
+
+/// Points at the opening quote of a string literal that never closes.
+fn unterminated_string(span: Span) -> Diagnostic {
+    Diagnostic::error(
+        ErrorKind::UnterminatedString,
+        Span { length: 1, ..span },
+        "unterminated string literal.",
+    )
+}
+
+/// The error for an unsuffixed literal that is only valid as an `i64`.
+pub fn too_large_for_i32(digits: &str, span: Span) -> Diagnostic {
+    Diagnostic::error(
+        ErrorKind::InvalidNumber,
+        span,
+        "integer literal does not fit in 'i32'.",
+    )
+    .with_help(format!("add an 'i64' suffix: '{}i64'.", digits))
+}
+
+fn check_number(number: &str, span: Span) -> Result<(), Diagnostic> {
+    let (digits, suffix) = split_number(number);
+    let fits = match suffix {
+        "" if digits.contains('.') => digits.parse::<f64>().is_ok(),
+        // The magnitude of each type's minimum is accepted, as the parser
+        // folds a preceding '-' into the literal and checks the result.
+        "" => match digits.parse::<i64>() {
+            Ok(n) if n <= 1 << 31 => true,
+            Ok(_) => return Err(too_large_for_i32(digits, span)),
+            Err(_) => false,
+        },
+        "i32" => digits.parse::<i64>().is_ok_and(|n| n <= 1 << 31),
+        "i64" => digits.parse::<i128>().is_ok_and(|n| n <= 1 << 63),
+        "f64" => digits.parse::<f64>().is_ok(),
+        _ => {
+            return Err(Diagnostic::error(
+                ErrorKind::InvalidNumber,
+                span,
+                format!("unknown literal suffix '{}'.", suffix),
+            )
+            .with_help("valid suffixes are 'i32', 'i64' and 'f64'."))
+        }
+    };
+    match fits {
+        true => Ok(()),
+        false => Err(Diagnostic::error(
+            ErrorKind::InvalidNumber,
+            span,
+            "invalid number format.",
+        )),
+    }
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, Diagnostic> {
     let mut tokens = vec![];
-    let mut iterator = input.chars().peekable();
+    let mut iterator = input.char_indices().peekable();
+    let mut line = 1;
+    let mut line_start = 0;
+    let mut trivia = Vec::new();
+
+    while let Some((start, char)) = iterator.next() {
+        let span = |iterator: &mut std::iter::Peekable<std::str::CharIndices>| {
+            let end = iterator.peek().map_or(input.len(), |(i, _)| *i);
+            Span {
+                offset: start,
+                length: end - start,
+                line,
+                column: input[line_start..start].chars().count() + 1,
+            }
+        };
 
-    while let Some(char) = iterator.next() {
-        let token = match char {
+        let mut token = match char {
             '+' => Token::from_type(TokenType::Plus),
             '-' => Token::from_type(TokenType::Minus),
-            '*' => Token::from_type(TokenType::Star),
-            '/' => Token::from_type(TokenType::Slash),
+            '*' => match iterator.next_if(|&(_, c)| c == '*') {
+                Some(_) => Token::from_type(TokenType::StarStar),
+                None => Token::from_type(TokenType::Star),
+            },
+            '/' => match iterator.next_if(|&(_, c)| c == '/' || c == '*') {
+                Some((_, '/')) => {
+                    while iterator.next_if(|&(_, c)| c != '\n').is_some() {}
+                    let span = span(&mut iterator);
+                    trivia.push(Comment {
+                        text: input[start..start + span.length].to_string(),
+                        span,
+                    });
+                    continue;
+                }
+                Some(_) => {
+                    // Newlines inside the comment are counted once its span,
+                    // which starts on the current line, is known.
+                    let mut newlines = 0;
+                    let mut last_line_start = line_start;
+                    loop {
+                        match iterator.next() {
+                            Some((_, '*')) if iterator.next_if(|&(_, c)| c == '/').is_some() => {
+                                break
+                            }
+                            Some((at, '\n')) => {
+                                newlines += 1;
+                                last_line_start = at + 1;
+                            }
+                            Some(_) => {}
+                            None => {
+                                return Err(Diagnostic::error(
+                                    ErrorKind::UnterminatedComment,
+                                    Span {
+                                        length: 2,
+                                        ..span(&mut iterator)
+                                    },
+                                    "unterminated block comment.",
+                                ))
+                            }
+                        }
+                    }
+                    let span = span(&mut iterator);
+                    trivia.push(Comment {
+                        text: input[start..start + span.length].to_string(),
+                        span,
+                    });
+                    line += newlines;
+                    line_start = last_line_start;
+                    continue;
+                }
+                None => Token::from_type(TokenType::Slash),
+            },
+            '%' => Token::from_type(TokenType::Percent),
+            '&' => Token::from_type(TokenType::Ampersand),
+            '|' => Token::from_type(TokenType::Pipe),
+            '^' => Token::from_type(TokenType::Caret),
+            '~' => Token::from_type(TokenType::Tilde),
             '(' => Token::from_type(TokenType::LeftParen),
             ')' => Token::from_type(TokenType::RightParen),
+            '{' => Token::from_type(TokenType::LeftBrace),
+            '}' => Token::from_type(TokenType::RightBrace),
+            ',' => Token::from_type(TokenType::Comma),
             ':' => Token::from_type(TokenType::Colon),
-            '=' => match iterator.next() {
-                Some('=') => Token::from_type(TokenType::EqualEqual),
-                _ => Token::from_type(TokenType::Equal),
+            '=' => match iterator.next_if(|&(_, c)| c == '=') {
+                Some(_) => Token::from_type(TokenType::EqualEqual),
+                None => Token::from_type(TokenType::Equal),
+            },
+            '!' => match iterator.next_if(|&(_, c)| c == '=') {
+                Some(_) => Token::from_type(TokenType::BangEqual),
+                None => {
+                    return Err(Diagnostic::error(
+                        ErrorKind::InvalidCharacter,
+                        span(&mut iterator),
+                        "invalid input.",
+                    ))
+                }
+            },
+            '<' => match iterator.next_if(|&(_, c)| c == '=' || c == '<') {
+                Some((_, '=')) => Token::from_type(TokenType::LessEqual),
+                Some(_) => Token::from_type(TokenType::LessLess),
+                None => Token::from_type(TokenType::Less),
+            },
+            '>' => match iterator.next_if(|&(_, c)| c == '=' || c == '>') {
+                Some((_, '=')) => Token::from_type(TokenType::GreaterEqual),
+                Some(_) => Token::from_type(TokenType::GreaterGreater),
+                None => Token::from_type(TokenType::Greater),
             },
             '0'..='9' => {
                 let mut number = String::from(char);
 
-                while let Some(char) = iterator.peek() {
+                while let Some(&(_, char)) = iterator.peek() {
                     match char {
-                        '0'..='9' | '.' => {
-                            number.push(*char);
+                        '0'..='9' | '.' | 'a'..='z' => {
+                            number.push(char);
                             iterator.next();
                         }
                         _ => break,
                     }
                 }
 
-                match number.parse::<i32>() {
-                    Ok(_) => Token::new(TokenType::Number, number),
-                    Err(_) => match number.parse::<f64>() {
-                        Ok(_) => Token::new(TokenType::Number, number),
-                        Err(_) => return Err("invalid number format."),
-                    },
+                check_number(&number, span(&mut iterator))?;
+                Token::new(TokenType::Number, number)
+            }
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match iterator.next() {
+                        Some((_, '"')) => break,
+                        Some((at, '\\')) => match iterator.next() {
+                            Some((_, '\n')) | None => {
+                                return Err(unterminated_string(span(&mut iterator)))
+                            }
+                            Some((_, c)) => match escape(c) {
+                                Some(escaped) => string.push(escaped),
+                                None => {
+                                    let column = input[line_start..at].chars().count() + 1;
+                                    return Err(invalid_escape(c, at, line, column));
+                                }
+                            },
+                        },
+                        Some((_, '\n')) | None => {
+                            return Err(unterminated_string(span(&mut iterator)))
+                        }
+                        Some((_, c)) => string.push(c),
+                    }
                 }
+                Token::new(TokenType::String, string)
             }
             'ა'..='ჰ' | 'a'..='z' | 'A'..='Z' => {
                 let mut identifier = String::from(char);
-                while let Some(char) = iterator.peek() {
+                while let Some(&(_, char)) = iterator.peek() {
                     match char {
                         'ა'..='ჰ' | 'a'..='z' | 'A'..='Z' | '0'..='9' => {
-                            identifier.push(*char);
+                            identifier.push(char);
                             iterator.next();
                         }
                         _ => break,
@@ -86,6 +340,17 @@ pub fn tokenize(input: &str) -> Result<Vec<Token>, &str> {
                 match identifier.as_str() {
                     "დაბეჭდე" => Token::from_type(TokenType::Print),
                     "ცვლადი" => Token::new(TokenType::Var, identifier),
+                    "თუ" => Token::from_type(TokenType::If),
+                    "თორემ" => Token::from_type(TokenType::Else),
+                    "სანამ" => Token::from_type(TokenType::While),
+                    "შეწყვიტე" => Token::from_type(TokenType::Break),
+                    "გააგრძელე" => Token::from_type(TokenType::Continue),
+                    "ფუნქცია" => Token::from_type(TokenType::Function),
+                    "დააბრუნე" => Token::from_type(TokenType::Return),
+                    "როგორც" => Token::from_type(TokenType::As),
+                    "და" => Token::from_type(TokenType::And),
+                    "ან" => Token::from_type(TokenType::Or),
+                    "არა" => Token::from_type(TokenType::Not),
                     _ => Token::new(TokenType::Identifier, identifier),
                 }
             }
@@ -94,9 +359,32 @@ pub fn tokenize(input: &str) -> Result<Vec<Token>, &str> {
                 continue;
             }
             _ => {
-                return Err("invalid input.");
+                return Err(Diagnostic::error(
+                    ErrorKind::InvalidCharacter,
+                    span(&mut iterator),
+                    "invalid input.",
+                ));
             }
         };
+        token.span = span(&mut iterator);
+        token.trivia = std::mem::take(&mut trivia);
+        if token.type_ == TokenType::Newline {
+            line += 1;
+            line_start = start + 1;
+        }
+        tokens.push(token);
+    }
+
+    // Comments after the last token are kept on a final, empty newline.
+    if !trivia.is_empty() {
+        let mut token = Token::from_type(TokenType::Newline);
+        token.span = Span {
+            offset: input.len(),
+            length: 0,
+            line,
+            column: input[line_start..].chars().count() + 1,
+        };
+        token.trivia = trivia;
         tokens.push(token);
     }
     Ok(tokens)
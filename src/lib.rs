@@ -1,3 +1,5 @@
 pub mod compiler;
+pub mod diagnostic;
+pub mod interpreter;
 pub mod lexer;
 pub mod parser;
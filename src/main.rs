@@ -1,51 +1,486 @@
-use aeiou::compiler::Compiler;
+use aeiou::compiler::{Compiler, RUNTIME_ERROR_STATUS};
+use aeiou::diagnostic::{Diagnostic, ErrorKind};
+use aeiou::interpreter::Interpreter;
 use aeiou::{lexer, parser};
-use std::{env, fs};
+use std::io::{self, Read};
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, fs, process};
 
-fn read_source() -> Result<String, &'static str> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        return Err("source file is required.");
+const USAGE: &str = "\
+Usage: aeiou <command> [options] <file>
+
+Commands:
+    build     compile <file> to an executable, object file or assembly
+    run       build <file> and run it, exiting with the program's status
+    check     lex, parse and type check <file> without compiling it
+    tokens    print the tokens of <file>
+    ast       print the syntax tree of <file>
+
+Options:
+    -o <path>          write the output of 'build' to <path>
+    --emit=<kind>      what 'build' produces: exe (default), obj or asm
+    --checked          stop with an error on overflow and division by zero
+    --interpret        have 'run' evaluate <file> directly instead of building it
+    -h, --help         print this message
+
+Pass '-' as <file> to read the program from standard input.
+
+Exit status:
+    0    success
+    2    the command line is invalid
+    3    a file could not be read or written, or a tool could not be started
+    4    the program has a lexical error
+    5    the program has a syntax error
+    6    the program has a type error
+    7    code generation, assembling or linking failed
+    101  a checked program, or one run with '--interpret', failed at runtime
+'run' exits with the status of the program instead when it gets to run it.
+";
+
+/// The exit status for a failure of `kind`, as listed in `USAGE`.
+fn exit_code(kind: ErrorKind) -> i32 {
+    match kind {
+        ErrorKind::Usage => 2,
+        ErrorKind::Io => 3,
+        ErrorKind::InvalidCharacter
+        | ErrorKind::InvalidNumber
+        | ErrorKind::UnterminatedString
+        | ErrorKind::InvalidEscape
+        | ErrorKind::UnterminatedComment => 4,
+        ErrorKind::UnexpectedToken | ErrorKind::MisplacedStatement => 5,
+        ErrorKind::TypeMismatch
+        | ErrorKind::UndeclaredVariable
+        | ErrorKind::UndeclaredFunction
+        | ErrorKind::AlreadyDeclared
+        | ErrorKind::UnknownType
+        | ErrorKind::WrongArgumentCount
+        | ErrorKind::TooManyParameters
+        | ErrorKind::MissingReturn => 6,
+        ErrorKind::Codegen => 7,
+        ErrorKind::Runtime => RUNTIME_ERROR_STATUS,
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Subcommand {
+    Build,
+    Run,
+    Check,
+    Tokens,
+    Ast,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Emit {
+    Asm,
+    Obj,
+    Exe,
+}
+
+struct Options {
+    subcommand: Subcommand,
+    input: String,
+    output: Option<String>,
+    emit: Emit,
+    checked: bool,
+    interpret: bool,
+}
+
+struct Source {
+    name: String,
+    text: String,
+}
+
+/// Parses the command line, returning `None` if help was asked for.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, Diagnostic> {
+    let subcommand = match args.next().as_deref() {
+        Some("build") => Subcommand::Build,
+        Some("run") => Subcommand::Run,
+        Some("check") => Subcommand::Check,
+        Some("tokens") => Subcommand::Tokens,
+        Some("ast") => Subcommand::Ast,
+        Some("-h" | "--help") => return Ok(None),
+        Some(other) => {
+            return Err(Diagnostic::global(
+                ErrorKind::Usage,
+                format!("unknown command '{}'.", other),
+            )
+            .with_help("run 'aeiou --help' to see the available commands."))
+        }
+        None => {
+            return Err(
+                Diagnostic::global(ErrorKind::Usage, "a command is required.")
+                    .with_help("run 'aeiou --help' to see the available commands."),
+            )
+        }
+    };
+    let mut input = None;
+    let mut output = None;
+    let mut emit = Emit::Exe;
+    let mut checked = false;
+    let mut interpret = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => match args.next() {
+                Some(path) => output = Some(path),
+                None => {
+                    return Err(Diagnostic::global(
+                        ErrorKind::Usage,
+                        "'-o' requires an output path.",
+                    ))
+                }
+            },
+            "--emit=asm" => emit = Emit::Asm,
+            "--emit=obj" => emit = Emit::Obj,
+            "--emit=exe" => emit = Emit::Exe,
+            "--checked" => checked = true,
+            "--interpret" => interpret = true,
+            "-h" | "--help" => return Ok(None),
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(Diagnostic::global(
+                    ErrorKind::Usage,
+                    format!("unknown option '{}'.", arg),
+                ))
+            }
+            _ if input.is_none() => input = Some(arg),
+            _ => {
+                return Err(Diagnostic::global(
+                    ErrorKind::Usage,
+                    "only one source file can be compiled at a time.",
+                ))
+            }
+        }
     }
-    let name = &args[1];
-    if !name.ends_with(".aeiou") {
-        return Err("source file should must have \".aeiou\" extension.");
+
+    match input {
+        Some(input) => Ok(Some(Options {
+            subcommand,
+            input,
+            output,
+            emit,
+            checked,
+            interpret,
+        })),
+        None => Err(Diagnostic::global(
+            ErrorKind::Usage,
+            "source file is required.",
+        )),
+    }
+}
+
+/// Reads the program from `name`, or from standard input if `name` is `-`.
+fn read_source(name: &str) -> Result<Source, Diagnostic> {
+    if name == "-" {
+        let mut text = String::new();
+        return match io::stdin().read_to_string(&mut text) {
+            Ok(_) => Ok(Source {
+                name: "<stdin>".to_string(),
+                text,
+            }),
+            Err(_) => Err(Diagnostic::global(
+                ErrorKind::Io,
+                "Could not read standard input.",
+            )),
+        };
     }
 
     match fs::read_to_string(name) {
-        Ok(str) => Ok(str),
-        Err(_) => Err("Could not read source file."),
+        Ok(text) => Ok(Source {
+            name: name.to_string(),
+            text,
+        }),
+        Err(_) => Err(Diagnostic::global(
+            ErrorKind::Io,
+            "Could not read source file.",
+        )),
     }
 }
 
-fn display_err(err: &str) {
-    eprintln!("\x1b[31m{err}\x1b[0m");
+/// The path to write to: `-o` if given, otherwise the source file's name with
+/// the extension of `emit`, in the current directory. Programs read from
+/// standard input are named `out`.
+fn output_path(options: &Options) -> PathBuf {
+    if let Some(output) = &options.output {
+        return PathBuf::from(output);
+    }
+    let path = match options.input.as_str() {
+        "-" => PathBuf::from("out"),
+        input => PathBuf::from(Path::new(input).file_stem().unwrap_or_default()),
+    };
+    match options.emit {
+        Emit::Asm => path.with_extension("asm"),
+        Emit::Obj => path.with_extension("o"),
+        Emit::Exe => path,
+    }
 }
 
-fn main() {
-    let source = read_source();
-    if let Err(err) = source {
-        display_err(err);
-        return;
+fn write(path: &Path, contents: &str) -> Result<(), Diagnostic> {
+    fs::write(path, contents).map_err(|err| {
+        Diagnostic::global(
+            ErrorKind::Io,
+            format!("Could not write '{}': {}.", path.display(), err),
+        )
+    })
+}
+
+/// Runs an external tool, turning a failure to start it or a non-zero exit
+/// into a diagnostic that carries the tool's error output.
+fn run_tool(command: &mut Command) -> Result<(), Diagnostic> {
+    let name = command.get_program().to_string_lossy().into_owned();
+    let output = command.output().map_err(|err| {
+        Diagnostic::global(ErrorKind::Io, format!("Could not run '{}': {}.", name, err))
+            .with_help("install it, or use '--emit=asm' and assemble the output yourself.")
+    })?;
+    if output.status.success() {
+        return Ok(());
     }
-    
-    let tokens = match lexer::tokenize(&source.unwrap()) {
-        Ok(result) => result,
-        Err(err) => {
-            display_err(err);
+    let mut diagnostic = Diagnostic::global(ErrorKind::Codegen, format!("'{}' failed.", name));
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        diagnostic = diagnostic.with_note(line);
+    }
+    Err(diagnostic)
+}
+
+/// A directory for intermediate files that only the current user can access,
+/// so no one else can replace or read them. It is removed when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Result<TempDir, Diagnostic> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.subsec_nanos());
+        let mut attempt = 0;
+        loop {
+            let name = format!("aeiou-{}-{}-{}", process::id(), nanos, attempt);
+            let path = env::temp_dir().join(name);
+            // Fails if the path exists, even as a symlink, so the directory
+            // is always a new one.
+            match fs::DirBuilder::new().mode(0o700).create(&path) {
+                Ok(()) => return Ok(TempDir(path)),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => {
+                    attempt += 1;
+                }
+                Err(err) => {
+                    return Err(Diagnostic::global(
+                        ErrorKind::Io,
+                        format!("Could not create '{}': {}.", path.display(), err),
+                    ))
+                }
+            }
+        }
+    }
+
+    fn join(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn assemble(asm: &str, object: &Path, temp: &TempDir) -> Result<(), Diagnostic> {
+    let source = temp.join("program.asm");
+    write(&source, asm)?;
+    run_tool(
+        Command::new("nasm")
+            .args(["-f", "elf64", "-o"])
+            .arg(object)
+            .arg(&source),
+    )
+}
+
+fn build(asm: &str, output: &Path, emit: Emit) -> Result<(), Diagnostic> {
+    match emit {
+        Emit::Asm => write(output, asm),
+        _ => build_in(asm, output, emit, &TempDir::new()?),
+    }
+}
+
+/// Builds `output`, keeping intermediate files in `temp`.
+fn build_in(asm: &str, output: &Path, emit: Emit, temp: &TempDir) -> Result<(), Diagnostic> {
+    match emit {
+        Emit::Asm => write(output, asm),
+        Emit::Obj => assemble(asm, output, temp),
+        Emit::Exe => {
+            let object = temp.join("program.o");
+            assemble(asm, &object, temp)?;
+            // The generated code uses absolute addresses.
+            run_tool(
+                Command::new("cc")
+                    .arg("-no-pie")
+                    .arg("-o")
+                    .arg(output)
+                    .arg(&object),
+            )
+        }
+    }
+}
+
+/// Builds a temporary executable, runs it with this process's standard
+/// streams and returns its exit status. A program killed by a signal reports
+/// `128 + signal`, as shells do.
+fn run(asm: &str) -> Result<i32, Diagnostic> {
+    let temp = TempDir::new()?;
+    let executable = temp.join("program");
+    build_in(asm, &executable, Emit::Exe, &temp)?;
+    let status = Command::new(&executable).status().map_err(|err| {
+        Diagnostic::global(
+            ErrorKind::Io,
+            format!("Could not run the program: {}.", err),
+        )
+    })?;
+    Ok(status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1))
+}
+
+/// Runs the program in the interpreter. Runtime errors are reported in the
+/// same words, and with the same status, as a compiled program reports them.
+fn interpret(program: Vec<parser::Stmt>, checked: bool, source: &Source) {
+    let mut interpreter = Interpreter::new(io::BufWriter::new(io::stdout())).checked(checked);
+    match interpreter.run(program) {
+        Ok(()) => {}
+        Err(err) if err.kind == ErrorKind::Runtime => {
+            let line = err.span.map_or(0, |span| span.line);
+            eprintln!("შეცდომა ხაზზე {}: {}", line, err.message);
+            process::exit(RUNTIME_ERROR_STATUS);
+        }
+        Err(err) => fail(&[err], Some(source)),
+    }
+}
+
+fn print_tokens(tokens: &[lexer::Token]) {
+    for token in tokens {
+        let span = token.span;
+        println!(
+            "{}:{}\t{:?}\t{:?}",
+            span.line, span.column, token.type_, token.string
+        );
+    }
+}
+
+fn display_err(diagnostic: &Diagnostic, source: Option<&Source>) {
+    let (source, span) = match (source, diagnostic.span) {
+        (Some(source), Some(span)) => (source, span),
+        _ => {
+            eprintln!(
+                "\x1b[31m{}: {}\x1b[0m",
+                diagnostic.severity, diagnostic.message
+            );
+            display_notes(diagnostic);
             return;
         }
     };
 
-    let parsed = parser::parse(tokens);
-    if let Err(err) = parsed {
-        display_err(err);
+    eprintln!(
+        "\x1b[31m{}:{}:{}: {}: {}\x1b[0m",
+        source.name, span.line, span.column, diagnostic.severity, diagnostic.message
+    );
+    let line = source.text.lines().nth(span.line - 1).unwrap_or("");
+    // Keep tabs so the caret lines up with the source line as printed.
+    let indent: String = line
+        .chars()
+        .take(span.column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = source.text[span.offset..span.offset + span.length]
+        .trim_end_matches(['\r', '\n'])
+        .chars()
+        .count()
+        .max(1);
+    eprintln!("{line}");
+    eprintln!("{indent}\x1b[31m{}\x1b[0m", "^".repeat(width));
+    display_notes(diagnostic);
+}
+
+fn display_notes(diagnostic: &Diagnostic) {
+    for note in &diagnostic.notes {
+        eprintln!("note: {note}");
+    }
+    if let Some(help) = &diagnostic.help {
+        eprintln!("help: {help}");
+    }
+}
+
+/// Reports `diagnostics` and exits. When several are reported, the status is
+/// that of the earliest stage that failed, so a syntax error wins over the
+/// type errors that may have followed from it.
+fn fail(diagnostics: &[Diagnostic], source: Option<&Source>) -> ! {
+    diagnostics
+        .iter()
+        .for_each(|diagnostic| display_err(diagnostic, source));
+    let code = diagnostics
+        .iter()
+        .map(|diagnostic| exit_code(diagnostic.kind))
+        .min()
+        .unwrap_or(1);
+    process::exit(code)
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{USAGE}");
+            return;
+        }
+        Err(err) => fail(&[err], None),
+    };
+
+    let source = match read_source(&options.input) {
+        Ok(source) => source,
+        Err(err) => fail(&[err], None),
+    };
+
+    let tokens = match lexer::tokenize(&source.text) {
+        Ok(result) => result,
+        Err(err) => fail(&[err], Some(&source)),
+    };
+
+    if options.subcommand == Subcommand::Tokens {
+        print_tokens(&tokens);
         return;
     }
 
-    let parsed = parsed.unwrap();
-    let mut c = Compiler::new();
-    if let Err(err) = c.compile(parsed) {
-        display_err(err.to_string().as_str());
+    let parsed = match parser::parse(tokens) {
+        Ok(result) => result,
+        Err(errors) => fail(&errors, Some(&source)),
+    };
+
+    match options.subcommand {
+        Subcommand::Check => return,
+        Subcommand::Ast => {
+            parsed.iter().for_each(|stmt| print!("{stmt}"));
+            return;
+        }
+        Subcommand::Run if options.interpret => {
+            interpret(parsed, options.checked, &source);
+            return;
+        }
+        _ => {}
+    }
+
+    let mut c = Compiler::new().checked(options.checked);
+    let asm = match c.compile(parsed) {
+        Ok(asm) => asm,
+        Err(err) => fail(&[err], Some(&source)),
+    };
+
+    let result = match options.subcommand {
+        Subcommand::Run => run(&asm).map(|status| process::exit(status)),
+        _ => build(&asm, &output_path(&options), options.emit),
+    };
+    if let Err(err) = result {
+        fail(&[err], None);
     }
 }
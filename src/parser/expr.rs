@@ -1,10 +1,83 @@
-use crate::lexer::TokenType;
+use crate::lexer::{Span, TokenType};
 use crate::parser::types::Type;
+use std::fmt::{Display, Formatter};
 
 #[derive(Debug)]
 pub enum Expr {
-    Binary(Type, Box<Expr>, TokenType, Box<Expr>),
-    Grouping(Type, Box<Expr>),
-    Unary(Type, Box<Expr>),
-    Literal(Type, String),
+    Binary(Type, Box<Expr>, TokenType, Box<Expr>, Span),
+    Grouping(Type, Box<Expr>, Span),
+    Unary(Type, Box<Expr>, Span),
+    Cast(Type, Box<Expr>, Span),
+    Logical(Type, Box<Expr>, TokenType, Box<Expr>, Span),
+    Not(Type, Box<Expr>, Span),
+    Literal(Type, String, Span),
+    Variable(Type, String, Span),
+    Call(Type, String, Vec<Expr>, Span),
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Binary(.., span)
+            | Expr::Grouping(.., span)
+            | Expr::Unary(.., span)
+            | Expr::Cast(.., span)
+            | Expr::Logical(.., span)
+            | Expr::Not(.., span)
+            | Expr::Literal(.., span)
+            | Expr::Variable(.., span)
+            | Expr::Call(.., span) => *span,
+        }
+    }
+}
+
+/// The source spelling of a binary or logical operator.
+fn operator(token: &TokenType) -> &'static str {
+    match token {
+        TokenType::Plus => "+",
+        TokenType::Minus => "-",
+        TokenType::Star => "*",
+        TokenType::StarStar => "**",
+        TokenType::Slash => "/",
+        TokenType::Percent => "%",
+        TokenType::Ampersand => "&",
+        TokenType::Pipe => "|",
+        TokenType::Caret => "^",
+        TokenType::EqualEqual => "==",
+        TokenType::BangEqual => "!=",
+        TokenType::Less => "<",
+        TokenType::LessEqual => "<=",
+        TokenType::LessLess => "<<",
+        TokenType::Greater => ">",
+        TokenType::GreaterEqual => ">=",
+        TokenType::GreaterGreater => ">>",
+        TokenType::And => "და",
+        TokenType::Or => "ან",
+        _ => "?",
+    }
+}
+
+/// Writes the expression as an S-expression, e.g. `(+ 1 (* 2 x))`.
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Binary(_, left, op, right, _) | Expr::Logical(_, left, op, right, _) => {
+                write!(f, "({} {} {})", operator(op), left, right)
+            }
+            Expr::Grouping(_, inner, _) => write!(f, "(group {})", inner),
+            Expr::Unary(_, inner, _) => write!(f, "(- {})", inner),
+            Expr::Cast(t, inner, _) => write!(f, "(როგორც {} {})", inner, t),
+            Expr::Not(Type::Bool, inner, _) => write!(f, "(არა {})", inner),
+            Expr::Not(_, inner, _) => write!(f, "(~ {})", inner),
+            Expr::Literal(Type::Str, value, _) => write!(f, "{:?}", value),
+            Expr::Literal(_, value, _) | Expr::Variable(_, value, _) => write!(f, "{}", value),
+            Expr::Call(_, name, args, _) => {
+                write!(f, "({}", name)?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
 }
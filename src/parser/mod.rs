@@ -2,153 +2,1076 @@ mod expr;
 mod stmt;
 mod types;
 
+use crate::diagnostic::{Diagnostic, ErrorKind};
 use crate::lexer::TokenType::{Equal, Identifier, Newline};
-use crate::lexer::{Token, TokenType};
+use crate::lexer::{self, Span, Token, TokenType};
 pub use crate::parser::expr::Expr;
 pub use crate::parser::stmt::Stmt;
 pub use crate::parser::types::Type;
-use std::iter::Peekable;
-use std::slice::Iter;
+use std::collections::HashMap;
 
-fn match_token(iter: &mut Peekable<Iter<Token>>, token_type: TokenType) -> Option<Token> {
-    let current = iter.next();
-    if current.is_none() || current.unwrap().type_ != token_type {
-        return None;
+type ParseResult<T> = Result<T, Diagnostic>;
+type Operand<'a> = fn(&mut Parser<'a>) -> ParseResult<(Type, Box<Expr>)>;
+
+/// Parameters are passed in registers, six general-purpose ones for integers,
+/// booleans and strings and eight SSE ones for `f64`.
+const MAX_GENERAL_PARAMETERS: usize = 6;
+const MAX_F64_PARAMETERS: usize = 8;
+
+/// The operand types a binary operator accepts.
+#[derive(Clone, Copy)]
+enum Operands {
+    Numeric,
+    Integer,
+    Comparable,
+    /// Booleans, for `და` and `ან`, which only evaluate their right operand
+    /// when needed.
+    Boolean,
+}
+
+impl Operands {
+    /// Checks the operand types of `operator`. Apart from the logical
+    /// operators, both sides must have the same type.
+    fn check(self, left: Type, right: Type, operator: Span) -> ParseResult<()> {
+        let note = format!("left operand is '{}', right operand is '{}'.", left, right);
+        let message = match self {
+            Operands::Boolean if left == Type::Bool && right == Type::Bool => return Ok(()),
+            Operands::Boolean => {
+                return Err(Diagnostic::error(
+                    ErrorKind::TypeMismatch,
+                    operator,
+                    "Expected boolean operands.",
+                )
+                .with_note(note))
+            }
+            _ if left != right => {
+                return Err(
+                    Diagnostic::error(ErrorKind::TypeMismatch, operator, "Type mismatch.")
+                        .with_note(note)
+                        .with_help("convert one of the operands with 'როგორც'."),
+                )
+            }
+            Operands::Numeric if !left.is_numeric() => "Expected a numeric operand.",
+            Operands::Integer if !left.is_integer() => "Expected integer operands.",
+            Operands::Comparable if left == Type::Str => "Strings cannot be compared.",
+            _ => return Ok(()),
+        };
+        Err(Diagnostic::error(
+            ErrorKind::TypeMismatch,
+            operator,
+            message,
+        ))
     }
-    Some(current.unwrap().clone())
 }
 
-fn primary(iter: &mut Peekable<Iter<Token>>) -> Result<(Type, Box<Expr>), &'static str> {
-    match iter.next() {
-        Some(Token {
-            type_: TokenType::LeftParen,
-            ..
-        }) => {
-            let (t, expr) = expression(iter)?;
-            match iter.next() {
-                Some(Token {
-                    type_: TokenType::RightParen,
-                    ..
-                }) => Ok((t, Box::new(Expr::Grouping(t, expr)))),
-                _ => Err("Expected closing ')'."),
+#[derive(Clone)]
+struct Tokens<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.position);
+        if token.is_some() {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next_if(&mut self, predicate: impl FnOnce(&Token) -> bool) -> Option<&'a Token> {
+        match self.peek() {
+            Some(token) if predicate(token) => self.next(),
+            _ => None,
+        }
+    }
+
+    fn previous(&self) -> Option<&'a Token> {
+        self.position.checked_sub(1).map(|i| &self.tokens[i])
+    }
+}
+
+struct Signature {
+    params: Vec<Type>,
+    return_type: Option<Type>,
+}
+
+type FunctionHeader = (
+    Vec<(String, Type)>,
+    HashMap<String, Option<Type>>,
+    Option<Type>,
+);
+
+struct Parser<'a> {
+    iter: Tokens<'a>,
+    /// Variables by scope. A variable whose declaration failed has no type,
+    /// and statements using it are dropped without further errors.
+    scopes: Vec<HashMap<String, Option<Type>>>,
+    /// Set when the current statement uses such a variable.
+    poisoned: bool,
+    loop_depth: usize,
+    /// Functions by name. A function whose header failed to parse has no
+    /// signature, and statements calling it are dropped like those using a
+    /// variable whose declaration failed.
+    functions: HashMap<String, Option<Signature>>,
+    in_function: bool,
+    return_type: Option<Type>,
+    errors: Vec<Diagnostic>,
+}
+
+fn is_integer_literal(token: &Token) -> bool {
+    let (digits, suffix) = lexer::split_number(&token.string);
+    token.type_ == TokenType::Number && !digits.contains('.') && suffix != "f64"
+}
+
+/// Whether every path through `statements` ends in a `დააბრუნე`.
+fn returns(statements: &[Stmt]) -> bool {
+    match statements.last() {
+        Some(Stmt::Return(..)) => true,
+        Some(Stmt::If(_, then_branch, Some(else_branch), _)) => {
+            returns(then_branch) && returns(else_branch)
+        }
+        _ => false,
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn new(tokens: &'a [Token]) -> Parser<'a> {
+        Parser {
+            iter: Tokens {
+                tokens,
+                position: 0,
+            },
+            scopes: vec![HashMap::new()],
+            poisoned: false,
+            loop_depth: 0,
+            functions: HashMap::new(),
+            in_function: false,
+            return_type: None,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Builds an error pointing at the most recently consumed token.
+    fn error(&self, kind: ErrorKind, message: &str) -> Diagnostic {
+        let span = self
+            .iter
+            .previous()
+            .map_or(Span::default(), |token| token.span);
+        Diagnostic::error(kind, span, message)
+    }
+
+    fn lookup(&self, name: &str) -> Option<Type> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+            .flatten()
+    }
+
+    /// Builds the error for a use of an undeclared variable, or, when its
+    /// declaration failed, marks the statement as poisoned.
+    fn undeclared(&mut self, name: &str, err: Diagnostic) -> Diagnostic {
+        let scope = self
+            .scopes
+            .iter()
+            .rev()
+            .find(|scope| scope.contains_key(name));
+        self.poisoned = scope.is_some();
+        err
+    }
+
+    fn declare(&mut self, name: &str, t: Option<Type>, span: Span) -> ParseResult<()> {
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.contains_key(name) {
+            return Err(Diagnostic::error(
+                ErrorKind::AlreadyDeclared,
+                span,
+                "Variable is already declared.",
+            ));
+        }
+        scope.insert(name.to_string(), t);
+        Ok(())
+    }
+
+    fn match_token(&mut self, token_type: TokenType) -> Option<Token> {
+        let current = self.iter.next();
+        if current.is_none() || current.unwrap().type_ != token_type {
+            return None;
+        }
+        Some(current.unwrap().clone())
+    }
+
+    fn is_assignment(&self) -> bool {
+        let mut lookahead = self.iter.clone();
+        lookahead.next();
+        matches!(lookahead.peek(), Some(Token { type_: Equal, .. }))
+    }
+
+    fn is_call(&self) -> bool {
+        let mut lookahead = self.iter.clone();
+        lookahead.next();
+        matches!(
+            lookahead.peek(),
+            Some(Token {
+                type_: TokenType::LeftParen,
+                ..
+            })
+        )
+    }
+
+    fn is_void_call(&self) -> bool {
+        match self.iter.clone().next() {
+            Some(token) if self.is_call() => {
+                self.functions.get(&token.string).is_some_and(|signature| {
+                    signature
+                        .as_ref()
+                        .is_none_or(|signature| signature.return_type.is_none())
+                })
             }
+            _ => false,
         }
-        Some(Token {
-            type_: TokenType::Minus,
-            ..
-        }) => {
-            let (t, expr) = primary(iter)?;
-            Ok((t, Box::new(Expr::Unary(t, expr))))
+    }
+
+    fn arguments(&mut self, name: &str, span: Span) -> ParseResult<(Option<Type>, Vec<Expr>)> {
+        if self.match_token(TokenType::LeftParen).is_none() {
+            return Err(self.error(ErrorKind::UnexpectedToken, "Expected opening '('."));
         }
-        Some(
-            token @ Token {
-                type_: TokenType::Number,
+
+        let mut arguments = Vec::new();
+        let mut types = Vec::new();
+        if self
+            .iter
+            .next_if(|token| token.type_ == TokenType::RightParen)
+            .is_none()
+        {
+            loop {
+                let (t, expr) = self.expression()?;
+                types.push(t);
+                arguments.push(*expr);
+                if self
+                    .iter
+                    .next_if(|token| token.type_ == TokenType::Comma)
+                    .is_none()
+                {
+                    break;
+                }
+            }
+            if self.match_token(TokenType::RightParen).is_none() {
+                return Err(self.error(ErrorKind::UnexpectedToken, "Expected closing ')'."));
+            }
+        }
+
+        let signature = match self.functions.get(name) {
+            Some(Some(signature)) => signature,
+            declared => {
+                // Declared without a signature when its header failed.
+                self.poisoned = declared.is_some();
+                return Err(Diagnostic::error(
+                    ErrorKind::UndeclaredFunction,
+                    span,
+                    "Undeclared function.",
+                ));
+            }
+        };
+        if signature.params.len() != arguments.len() {
+            return Err(Diagnostic::error(
+                ErrorKind::WrongArgumentCount,
+                span,
+                "Wrong number of arguments.",
+            ));
+        }
+        if signature.params != types {
+            return Err(Diagnostic::error(
+                ErrorKind::TypeMismatch,
+                span,
+                "Type mismatch.",
+            ));
+        }
+
+        Ok((signature.return_type, arguments))
+    }
+
+    fn type_annotation(&mut self) -> ParseResult<Type> {
+        match self.match_token(Identifier) {
+            Some(token) => Type::from_name(&token.string)
+                .ok_or_else(|| self.error(ErrorKind::UnknownType, "Unknown type.")),
+            None => Err(self.error(ErrorKind::UnexpectedToken, "Expected a type.")),
+        }
+    }
+
+    fn primary(&mut self) -> ParseResult<(Type, Box<Expr>)> {
+        let span = self.iter.peek().map_or(Span::default(), |token| token.span);
+        match self.iter.next() {
+            Some(Token {
+                type_: TokenType::LeftParen,
                 ..
+            }) => {
+                let (t, expr) = self.expression()?;
+                match self.iter.next() {
+                    Some(Token {
+                        type_: TokenType::RightParen,
+                        ..
+                    }) => Ok((t, Box::new(Expr::Grouping(t, expr, span)))),
+                    _ => Err(self.error(ErrorKind::UnexpectedToken, "Expected closing ')'.")),
+                }
+            }
+            Some(Token {
+                type_: TokenType::Minus,
+                ..
+            }) => {
+                if let Some(token) = self.iter.next_if(is_integer_literal) {
+                    let length = token.span.offset + token.span.length - span.offset;
+                    return self.number(token, true, Span { length, ..span });
+                }
+                let (t, expr) = self.primary()?;
+                if !t.is_numeric() {
+                    return Err(Diagnostic::error(
+                        ErrorKind::TypeMismatch,
+                        span,
+                        "Expected a numeric operand.",
+                    ));
+                }
+                Ok((t, Box::new(Expr::Unary(t, expr, span))))
+            }
+            Some(Token {
+                type_: TokenType::Tilde,
+                ..
+            }) => {
+                let (t, expr) = self.primary()?;
+                if !t.is_integer() {
+                    return Err(Diagnostic::error(
+                        ErrorKind::TypeMismatch,
+                        span,
+                        "Expected an integer operand.",
+                    ));
+                }
+                Ok((t, Box::new(Expr::Not(t, expr, span))))
+            }
+            Some(
+                token @ Token {
+                    type_: TokenType::Number,
+                    ..
+                },
+            ) => self.number(token, false, span),
+            Some(
+                token @ Token {
+                    type_: TokenType::String,
+                    ..
+                },
+            ) => Ok((
+                Type::Str,
+                Box::new(Expr::Literal(Type::Str, token.string.clone(), span)),
+            )),
+            Some(
+                token @ Token {
+                    type_: TokenType::Identifier,
+                    ..
+                },
+            ) if self
+                .iter
+                .peek()
+                .is_some_and(|next| next.type_ == TokenType::LeftParen) =>
+            {
+                match self.arguments(&token.string, span)? {
+                    (Some(t), arguments) => Ok((
+                        t,
+                        Box::new(Expr::Call(t, token.string.clone(), arguments, span)),
+                    )),
+                    (None, _) => Err(Diagnostic::error(
+                        ErrorKind::TypeMismatch,
+                        span,
+                        "Function does not return a value.",
+                    )),
+                }
+            }
+            Some(
+                token @ Token {
+                    type_: TokenType::Identifier,
+                    ..
+                },
+            ) => match self.lookup(&token.string) {
+                Some(t) => Ok((t, Box::new(Expr::Variable(t, token.string.clone(), span)))),
+                None => Err(self.undeclared(
+                    &token.string,
+                    Diagnostic::error(ErrorKind::UndeclaredVariable, span, "Undeclared variable."),
+                )),
             },
-        ) => {
-            let type_ = match token.string.contains(".") {
-                true => Type::F64,
-                false => Type::I32,
+            _ => Err(self.error(ErrorKind::UnexpectedToken, "Expected an expression.")),
+        }
+    }
+
+    /// A numeric literal, negated when `negative`. Negative integers are
+    /// single literals, since the magnitude of a type's minimum doesn't fit
+    /// in it.
+    fn number(&self, token: &Token, negative: bool, span: Span) -> ParseResult<(Type, Box<Expr>)> {
+        let (digits, suffix) = lexer::split_number(&token.string);
+        let type_ = match Type::from_name(suffix) {
+            Some(t) => t,
+            None if digits.contains('.') => Type::F64,
+            None => Type::I32,
+        };
+        let mut literal = match negative {
+            true => format!("-{}", digits),
+            false => digits.to_string(),
+        };
+        let fits = match type_ {
+            Type::I32 => literal.parse::<i32>().is_ok(),
+            Type::I64 => literal.parse::<i64>().is_ok(),
+            _ => true,
+        };
+        // Only the magnitude of a minimum gets here without fitting.
+        match (fits, suffix) {
+            (true, _) => {}
+            (false, "") => return Err(lexer::too_large_for_i32(digits, token.span)),
+            (false, _) => {
+                return Err(Diagnostic::error(
+                    ErrorKind::InvalidNumber,
+                    token.span,
+                    "invalid number format.",
+                ))
+            }
+        }
+        if type_ == Type::F64 && !literal.contains('.') {
+            literal.push_str(".0");
+        }
+        Ok((type_, Box::new(Expr::Literal(type_, literal, span))))
+    }
+
+    fn cast(&mut self) -> ParseResult<(Type, Box<Expr>)> {
+        let (mut t, mut expr) = self.primary()?;
+
+        while let Some(operator) = self.iter.next_if(|token| token.type_ == TokenType::As) {
+            let target = self.type_annotation()?;
+            if !t.can_cast_to(&target) {
+                return Err(Diagnostic::error(
+                    ErrorKind::TypeMismatch,
+                    operator.span,
+                    format!("Cannot convert '{}' to '{}'.", t, target),
+                ));
+            }
+            t = target;
+            expr = Box::new(Expr::Cast(t, expr, operator.span));
+        }
+
+        Ok((t, expr))
+    }
+
+    /// Exponentiation is right-associative and always takes an `i32` exponent.
+    fn power(&mut self) -> ParseResult<(Type, Box<Expr>)> {
+        let (base_t, base_expr) = self.cast()?;
+
+        match self
+            .iter
+            .next_if(|token| token.type_ == TokenType::StarStar)
+        {
+            Some(operator) => {
+                let (exponent_t, exponent_expr) = self.power()?;
+                if !base_t.is_numeric() {
+                    return Err(Diagnostic::error(
+                        ErrorKind::TypeMismatch,
+                        operator.span,
+                        "Expected a numeric operand.",
+                    ));
+                }
+                if exponent_t != Type::I32 {
+                    return Err(Diagnostic::error(
+                        ErrorKind::TypeMismatch,
+                        operator.span,
+                        "Exponent must be 'i32'.",
+                    )
+                    .with_note(format!("exponent is '{}'.", exponent_t)));
+                }
+                Ok((
+                    base_t,
+                    Box::new(Expr::Binary(
+                        base_t,
+                        base_expr,
+                        operator.type_.clone(),
+                        exponent_expr,
+                        operator.span,
+                    )),
+                ))
+            }
+            None => Ok((base_t, base_expr)),
+        }
+    }
+
+    /// Parses a left-associative chain of `operand`s joined by `operators`,
+    /// whose types `accepted` checks. Comparisons produce a `bool`, every
+    /// other operator the operand type, and `Operands::Boolean` builds
+    /// short-circuiting `Expr::Logical` nodes.
+    fn binary(
+        &mut self,
+        operand: Operand<'a>,
+        operators: &[TokenType],
+        accepted: Operands,
+    ) -> ParseResult<(Type, Box<Expr>)> {
+        let (mut left_t, mut left_expr) = operand(self)?;
+
+        while let Some(operator) = self.iter.next_if(|token| operators.contains(&token.type_)) {
+            let (right_t, right_expr) = operand(self)?;
+            accepted.check(left_t, right_t, operator.span)?;
+            if matches!(
+                operator.type_,
+                TokenType::Less
+                    | TokenType::LessEqual
+                    | TokenType::Greater
+                    | TokenType::GreaterEqual
+                    | TokenType::EqualEqual
+                    | TokenType::BangEqual
+            ) {
+                left_t = Type::Bool;
+            }
+            let node = match accepted {
+                Operands::Boolean => Expr::Logical,
+                _ => Expr::Binary,
             };
-            Ok((type_, Box::new(Expr::Literal(type_, token.string.clone()))))
+            left_expr = Box::new(node(
+                left_t,
+                left_expr,
+                operator.type_.clone(),
+                right_expr,
+                operator.span,
+            ));
         }
-        _ => Err("Expected an expression."),
+
+        Ok((left_t, left_expr))
+    }
+
+    fn factor(&mut self) -> ParseResult<(Type, Box<Expr>)> {
+        use TokenType::{Percent, Slash, Star};
+        self.binary(Self::power, &[Star, Slash, Percent], Operands::Numeric)
+    }
+
+    fn term(&mut self) -> ParseResult<(Type, Box<Expr>)> {
+        use TokenType::{Minus, Plus};
+        self.binary(Self::factor, &[Plus, Minus], Operands::Numeric)
+    }
+
+    fn shift(&mut self) -> ParseResult<(Type, Box<Expr>)> {
+        use TokenType::{GreaterGreater, LessLess};
+        self.binary(Self::term, &[LessLess, GreaterGreater], Operands::Integer)
+    }
+
+    fn bit_and(&mut self) -> ParseResult<(Type, Box<Expr>)> {
+        self.binary(Self::shift, &[TokenType::Ampersand], Operands::Integer)
     }
-}
 
-fn factor(iter: &mut Peekable<Iter<Token>>) -> Result<(Type, Box<Expr>), &'static str> {
-    let (left_t, mut left_expr) = primary(iter)?;
-    if iter.peek().is_none() {
-        return Ok((left_t, left_expr));
+    fn bit_xor(&mut self) -> ParseResult<(Type, Box<Expr>)> {
+        self.binary(Self::bit_and, &[TokenType::Caret], Operands::Integer)
     }
 
-    while let TokenType::Star | TokenType::Slash = iter.peek().unwrap().type_ {
-        let operator = iter.next().unwrap();
-        let (right_t, right_expr) = primary(iter)?;
-        if left_t != right_t {
-            return Err("Type mismatch.");
+    fn bit_or(&mut self) -> ParseResult<(Type, Box<Expr>)> {
+        self.binary(Self::bit_xor, &[TokenType::Pipe], Operands::Integer)
+    }
+
+    fn comparison(&mut self) -> ParseResult<(Type, Box<Expr>)> {
+        use TokenType::{Greater, GreaterEqual, Less, LessEqual};
+        let operators = [Less, LessEqual, Greater, GreaterEqual];
+        self.binary(Self::bit_or, &operators, Operands::Numeric)
+    }
+
+    fn equality(&mut self) -> ParseResult<(Type, Box<Expr>)> {
+        use TokenType::{BangEqual, EqualEqual};
+        let operators = [EqualEqual, BangEqual];
+        self.binary(Self::comparison, &operators, Operands::Comparable)
+    }
+
+    fn not(&mut self) -> ParseResult<(Type, Box<Expr>)> {
+        match self.iter.next_if(|token| token.type_ == TokenType::Not) {
+            Some(operator) => {
+                let (t, expr) = self.not()?;
+                if t != Type::Bool {
+                    return Err(Diagnostic::error(
+                        ErrorKind::TypeMismatch,
+                        operator.span,
+                        "Expected a boolean operand.",
+                    )
+                    .with_note(format!("operand is '{}'.", t)));
+                }
+                Ok((t, Box::new(Expr::Not(t, expr, operator.span))))
+            }
+            None => self.equality(),
         }
-        left_expr = Box::new(Expr::Binary(
-            left_t,
-            left_expr,
-            operator.type_.clone(),
-            right_expr,
-        ));
     }
 
-    Ok((left_t, left_expr))
-}
+    fn and(&mut self) -> ParseResult<(Type, Box<Expr>)> {
+        self.binary(Self::not, &[TokenType::And], Operands::Boolean)
+    }
 
-fn term(iter: &mut Peekable<Iter<Token>>) -> Result<(Type, Box<Expr>), &'static str> {
-    let (left_t, mut left_expr) = factor(iter)?;
-    if iter.peek().is_none() {
-        return Ok((left_t, left_expr));
+    fn or(&mut self) -> ParseResult<(Type, Box<Expr>)> {
+        self.binary(Self::and, &[TokenType::Or], Operands::Boolean)
     }
 
-    while let TokenType::Plus | TokenType::Minus = iter.peek().unwrap().type_ {
-        let operator = iter.next().unwrap();
-        let (right_t, right_expr) = factor(iter)?;
-        if left_t != right_t {
-            return Err("Type mismatch.");
+    fn expression(&mut self) -> ParseResult<(Type, Box<Expr>)> {
+        self.or()
+    }
+
+    fn end_of_statement(&mut self) -> ParseResult<()> {
+        match self.iter.peek() {
+            None
+            | Some(Token {
+                type_: TokenType::RightBrace,
+                ..
+            }) => Ok(()),
+            Some(Token { type_: Newline, .. }) => {
+                self.iter.next();
+                Ok(())
+            }
+            Some(token) => Err(Diagnostic::error(
+                ErrorKind::UnexpectedToken,
+                token.span,
+                "Expected a newline.",
+            )),
         }
-        left_expr = Box::new(Expr::Binary(
-            left_t,
-            left_expr,
-            operator.type_.clone(),
-            right_expr,
-        ));
     }
 
-    Ok((left_t, left_expr))
-}
+    /// Records `err` and skips to the start of the next statement. Blocks opened
+    /// on the skipped lines are skipped whole so their bodies don't produce
+    /// follow-up errors, while a stray `}` is left for the enclosing block.
+    fn recover(&mut self, err: Diagnostic) {
+        if !std::mem::take(&mut self.poisoned) {
+            self.errors.push(err);
+        }
+        let mut depth = match self.iter.previous().map(|token| &token.type_) {
+            Some(Newline) => return,
+            Some(TokenType::LeftBrace) => 1,
+            _ => 0,
+        };
+        while let Some(token) = self.iter.peek() {
+            match token.type_ {
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace if depth == 0 => return,
+                TokenType::RightBrace => depth -= 1,
+                Newline if depth == 0 => {
+                    self.iter.next();
+                    return;
+                }
+                _ => {}
+            }
+            self.iter.next();
+        }
+    }
 
-fn expression(iter: &mut Peekable<Iter<Token>>) -> Result<(Type, Box<Expr>), &'static str> {
-    term(iter)
-}
+    fn skip_newlines(&mut self) {
+        while self.iter.next_if(|token| token.type_ == Newline).is_some() {}
+    }
 
-fn declaration(iter: &mut Peekable<Iter<Token>>) -> Result<Vec<Stmt>, &'static str> {
-    let mut statements = Vec::new();
-    while iter.peek().is_some() {
-        match iter.peek().unwrap().type_ {
-            TokenType::Print => {
-                iter.next();
-                let (_t, expr) = expression(iter)?;
-                if match_token(iter, Newline).is_none() {
-                    return Err("Expected a newline.");
+    fn block(&mut self) -> ParseResult<Vec<Stmt>> {
+        if self.match_token(TokenType::LeftBrace).is_none() {
+            return Err(self.error(ErrorKind::UnexpectedToken, "Expected opening '{'."));
+        }
+
+        self.scopes.push(HashMap::new());
+        let mut statements = Vec::new();
+        loop {
+            self.skip_newlines();
+            match self.iter.peek() {
+                Some(Token {
+                    type_: TokenType::RightBrace,
+                    ..
+                }) => {
+                    self.iter.next();
+                    break;
+                }
+                Some(_) => match self.statement() {
+                    Ok(statement) => statements.push(statement),
+                    Err(err) => self.recover(err),
+                },
+                None => {
+                    self.scopes.pop();
+                    return Err(self.error(ErrorKind::UnexpectedToken, "Expected closing '}'."));
                 }
-                statements.push(Stmt::Print(expr));
             }
-            TokenType::Var => {
-                iter.next();
-                if match_token(iter, Identifier).is_none() {
-                    return Err("Expected an identifier.");
+        }
+        self.scopes.pop();
+
+        Ok(statements)
+    }
+
+    fn if_statement(&mut self, span: Span) -> ParseResult<Stmt> {
+        let (t, condition) = self.expression()?;
+        if t != Type::Bool {
+            return Err(Diagnostic::error(
+                ErrorKind::TypeMismatch,
+                condition.span(),
+                "Expected a boolean condition.",
+            ));
+        }
+        let then_branch = self.block()?;
+
+        let else_branch = match self.iter.next_if(|token| token.type_ == TokenType::Else) {
+            Some(_) => match self.iter.next_if(|token| token.type_ == TokenType::If) {
+                Some(token) => Some(vec![self.if_statement(token.span)?]),
+                None => Some(self.block()?),
+            },
+            None => None,
+        };
+
+        Ok(Stmt::If(condition, then_branch, else_branch, span))
+    }
+
+    fn while_statement(&mut self, span: Span) -> ParseResult<Stmt> {
+        let (t, condition) = self.expression()?;
+        if t != Type::Bool {
+            return Err(Diagnostic::error(
+                ErrorKind::TypeMismatch,
+                condition.span(),
+                "Expected a boolean condition.",
+            ));
+        }
+
+        self.loop_depth += 1;
+        let body = self.block();
+        self.loop_depth -= 1;
+
+        Ok(Stmt::While(condition, body?, span))
+    }
+
+    /// Parses a function's parameters and return type, returning the
+    /// parameters with the scope they start its body with.
+    fn function_header(&mut self) -> ParseResult<FunctionHeader> {
+        if self.match_token(TokenType::LeftParen).is_none() {
+            return Err(self.error(ErrorKind::UnexpectedToken, "Expected opening '('."));
+        }
+        let mut params: Vec<(String, Type)> = Vec::new();
+        let mut scope = HashMap::new();
+        while self
+            .iter
+            .next_if(|token| token.type_ == TokenType::RightParen)
+            .is_none()
+        {
+            if !params.is_empty() && self.match_token(TokenType::Comma).is_none() {
+                return Err(self.error(ErrorKind::UnexpectedToken, "Expected a comma."));
+            }
+            let (param, param_span) = match self.match_token(Identifier) {
+                Some(token) => (token.string, token.span),
+                None => {
+                    return Err(self.error(ErrorKind::UnexpectedToken, "Expected a parameter name."))
                 }
+            };
+            if self.match_token(TokenType::Colon).is_none() {
+                return Err(self.error(ErrorKind::UnexpectedToken, "Expected a parameter type."));
+            }
+            let t = self.type_annotation()?;
+            if scope.insert(param.clone(), Some(t)).is_some() {
+                return Err(
+                    self.error(ErrorKind::AlreadyDeclared, "Parameter is already declared.")
+                );
+            }
+            params.push((param, t));
 
-                let name = match_token(iter, Equal);
-                if name.is_none() {
-                    return Err("Expected an equal operator.");
+            let same_class = params
+                .iter()
+                .filter(|(_, other)| (*other == Type::F64) == (t == Type::F64))
+                .count();
+            let limit = match t {
+                Type::F64 => MAX_F64_PARAMETERS,
+                _ => MAX_GENERAL_PARAMETERS,
+            };
+            // Reported once per class, and the function is still declared so
+            // its calls don't produce follow-up errors.
+            if same_class == limit + 1 {
+                self.errors.push(
+                    Diagnostic::error(
+                        ErrorKind::TooManyParameters,
+                        param_span,
+                        "Too many parameters.",
+                    )
+                    .with_note(format!(
+                        "at most {} 'f64' parameters and {} of other types are supported.",
+                        MAX_F64_PARAMETERS, MAX_GENERAL_PARAMETERS
+                    )),
+                );
+            }
+        }
+
+        let return_type = match self.iter.next_if(|token| token.type_ == TokenType::Colon) {
+            Some(_) => Some(self.type_annotation()?),
+            None => None,
+        };
+        Ok((params, scope, return_type))
+    }
+
+    fn function_declaration(&mut self, span: Span) -> ParseResult<Stmt> {
+        if self.in_function || self.scopes.len() > 1 {
+            return Err(Diagnostic::error(
+                ErrorKind::MisplacedStatement,
+                span,
+                "Functions can only be declared at the top level.",
+            ));
+        }
+
+        let name = match self.match_token(Identifier) {
+            Some(token) => token.string,
+            None => return Err(self.error(ErrorKind::UnexpectedToken, "Expected a function name.")),
+        };
+        if self.functions.contains_key(&name) {
+            return Err(self.error(ErrorKind::AlreadyDeclared, "Function is already declared."));
+        }
+
+        let (params, scope, return_type) = match self.function_header() {
+            Ok(header) => header,
+            Err(err) => {
+                // Declared without a signature, so calls to it are dropped
+                // instead of reported as undeclared.
+                self.functions.insert(name, None);
+                return Err(err);
+            }
+        };
+        self.functions.insert(
+            name.clone(),
+            Some(Signature {
+                params: params.iter().map(|(_, t)| *t).collect(),
+                return_type,
+            }),
+        );
+
+        let scopes = std::mem::replace(&mut self.scopes, vec![scope]);
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        self.in_function = true;
+        self.return_type = return_type;
+        let body = self.block();
+        self.scopes = scopes;
+        self.loop_depth = loop_depth;
+        self.in_function = false;
+        self.return_type = None;
+
+        let body = body?;
+        if return_type.is_some() && !returns(&body) {
+            return Err(Diagnostic::error(
+                ErrorKind::MissingReturn,
+                span,
+                "Function must return a value on every path.",
+            ));
+        }
+        Ok(Stmt::Function(name, params, return_type, body, span))
+    }
+
+    fn return_statement(&mut self, span: Span) -> ParseResult<Stmt> {
+        if !self.in_function {
+            return Err(Diagnostic::error(
+                ErrorKind::MisplacedStatement,
+                span,
+                "'დააბრუნე' is only allowed inside a function.",
+            ));
+        }
+
+        let value = match self.iter.peek() {
+            None
+            | Some(Token {
+                type_: TokenType::Newline | TokenType::RightBrace,
+                ..
+            }) => None,
+            _ => Some(self.expression()?),
+        };
+        match (self.return_type, value) {
+            (None, None) => Ok(Stmt::Return(None, span)),
+            (Some(expected), Some((t, expr))) if expected == t => {
+                Ok(Stmt::Return(Some(expr), span))
+            }
+            (Some(_), Some((_, expr))) => Err(Diagnostic::error(
+                ErrorKind::TypeMismatch,
+                expr.span(),
+                "Type mismatch.",
+            )),
+            (Some(_), None) => Err(Diagnostic::error(
+                ErrorKind::MissingReturn,
+                span,
+                "Expected a return value.",
+            )),
+            (None, Some(_)) => Err(Diagnostic::error(
+                ErrorKind::TypeMismatch,
+                span,
+                "Function does not return a value.",
+            )),
+        }
+    }
+
+    /// Parses the optional type annotation and the value of a `ცვლადი`
+    /// declaration, storing the annotation in `annotation` as soon as it is
+    /// known.
+    fn initializer(&mut self, annotation: &mut Option<Type>) -> ParseResult<(Type, Box<Expr>)> {
+        if self
+            .iter
+            .next_if(|token| token.type_ == TokenType::Colon)
+            .is_some()
+        {
+            *annotation = Some(self.type_annotation()?);
+        }
+
+        if self.match_token(Equal).is_none() {
+            return Err(self.error(ErrorKind::UnexpectedToken, "Expected an equal operator."));
+        }
+
+        let (t, expr) = self.expression()?;
+        if let Some(annotated) = annotation.filter(|annotated| *annotated != t) {
+            let err = Diagnostic::error(ErrorKind::TypeMismatch, expr.span(), "Type mismatch.")
+                .with_note(format!(
+                    "variable is annotated as '{}', but the value is '{}'.",
+                    annotated, t
+                ));
+            return Err(match expr.as_ref() {
+                Expr::Literal(Type::I32, literal, _) if annotated.is_numeric() => {
+                    err.with_help(format!(
+                        "add the '{}' suffix: '{}{}'.",
+                        annotated, literal, annotated
+                    ))
+                }
+                _ if t.can_cast_to(&annotated) => {
+                    err.with_help(format!("convert it with 'როგორც {}'.", annotated))
                 }
+                _ => err,
+            });
+        }
+        Ok((t, expr))
+    }
 
-                let (t, expr) = expression(iter)?;
-                if match_token(iter, Newline).is_none() {
-                    return Err("Expected a newline.");
+    fn statement(&mut self) -> ParseResult<Stmt> {
+        let span = self.iter.peek().unwrap().span;
+        let statement = match self.iter.peek().unwrap().type_.clone() {
+            TokenType::Print => {
+                self.iter.next();
+                let (_t, expr) = self.expression()?;
+                Stmt::Print(expr, span)
+            }
+            TokenType::Var => {
+                self.iter.next();
+                let name = match self.match_token(Identifier) {
+                    Some(name) => name,
+                    None => {
+                        return Err(
+                            self.error(ErrorKind::UnexpectedToken, "Expected an identifier.")
+                        )
+                    }
+                };
+
+                let mut annotation = None;
+                match self.initializer(&mut annotation) {
+                    Ok((t, expr)) => {
+                        self.declare(&name.string, Some(t), name.span)?;
+                        Stmt::Var(t, name.string, expr, span)
+                    }
+                    Err(err) => {
+                        // Declare it anyway, with the annotated type if there
+                        // is one, so later uses don't report it as undeclared.
+                        let _ = self.declare(&name.string, annotation, name.span);
+                        return Err(err);
+                    }
                 }
-                statements.push(Stmt::Var(t, name.unwrap().string, expr));
             }
-            _ => {
-                let (_t, expr) = expression(iter)?;
-                if match_token(iter, Newline).is_none() {
-                    return Err("Expected a newline.");
+            TokenType::Identifier if self.is_assignment() => {
+                let name = self.iter.next().unwrap().string.clone();
+                self.iter.next();
+
+                let (t, expr) = self.expression()?;
+                match self.lookup(&name) {
+                    Some(declared) if declared != t => {
+                        return Err(Diagnostic::error(
+                            ErrorKind::TypeMismatch,
+                            expr.span(),
+                            "Type mismatch.",
+                        )
+                        .with_note(format!(
+                            "'{}' is declared as '{}', but the value is '{}'.",
+                            name, declared, t
+                        )))
+                    }
+                    Some(_) => {}
+                    None => {
+                        let err = Diagnostic::error(
+                            ErrorKind::UndeclaredVariable,
+                            span,
+                            "Assignment to an undeclared variable.",
+                        )
+                        .with_help(format!("declare it first with 'ცვლადი {} = ...'.", name));
+                        return Err(self.undeclared(&name, err));
+                    }
                 }
-                statements.push(Stmt::Expression(expr));
+                Stmt::Assign(t, name, expr, span)
             }
-        }
+            TokenType::Identifier if self.is_void_call() => {
+                let name = self.iter.next().unwrap().string.clone();
+                let (_, arguments) = self.arguments(&name, span)?;
+                Stmt::Call(name, arguments, span)
+            }
+            TokenType::If => {
+                self.iter.next();
+                self.if_statement(span)?
+            }
+            TokenType::Function => {
+                self.iter.next();
+                self.function_declaration(span)?
+            }
+            TokenType::Return => {
+                self.iter.next();
+                self.return_statement(span)?
+            }
+            TokenType::While => {
+                self.iter.next();
+                self.while_statement(span)?
+            }
+            TokenType::Break => {
+                self.iter.next();
+                if self.loop_depth == 0 {
+                    return Err(Diagnostic::error(
+                        ErrorKind::MisplacedStatement,
+                        span,
+                        "'შეწყვიტე' is only allowed inside a loop.",
+                    ));
+                }
+                Stmt::Break(span)
+            }
+            TokenType::Continue => {
+                self.iter.next();
+                if self.loop_depth == 0 {
+                    return Err(Diagnostic::error(
+                        ErrorKind::MisplacedStatement,
+                        span,
+                        "'გააგრძელე' is only allowed inside a loop.",
+                    ));
+                }
+                Stmt::Continue(span)
+            }
+            _ => {
+                let (_t, expr) = self.expression()?;
+                Stmt::Expression(expr, span)
+            }
+        };
+        self.end_of_statement()?;
+
+        Ok(statement)
     }
 
-    Ok(statements)
+    fn declaration(&mut self) -> Vec<Stmt> {
+        let mut statements = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.iter.peek().is_none() {
+                break;
+            }
+            match self.statement() {
+                Ok(statement) => statements.push(statement),
+                Err(err) => self.recover(err),
+            }
+        }
+
+        statements
+    }
 }
 
-pub fn parse(tokens: Vec<Token>) -> Result<Vec<Stmt>, &'static str> {
-    let mut iter = tokens.iter().peekable();
-    declaration(&mut iter)
+/// Parses the whole token stream, collecting every error instead of stopping
+/// at the first one.
+pub fn parse(tokens: Vec<Token>) -> Result<Vec<Stmt>, Vec<Diagnostic>> {
+    let mut parser = Parser::new(&tokens);
+    let statements = parser.declaration();
+    match parser.errors.is_empty() {
+        true => Ok(statements),
+        false => Err(parser.errors),
+    }
 }
@@ -1,7 +1,81 @@
+use crate::lexer::Span;
 use crate::parser::{Expr, Type};
+use std::fmt::{Display, Formatter};
 
+#[derive(Debug)]
 pub enum Stmt {
-    Var(Type, String, Box<Expr>),
-    Print(Box<Expr>),
-    Expression(Box<Expr>),
+    Var(Type, String, Box<Expr>, Span),
+    Assign(Type, String, Box<Expr>, Span),
+    Print(Box<Expr>, Span),
+    Expression(Box<Expr>, Span),
+    If(Box<Expr>, Vec<Stmt>, Option<Vec<Stmt>>, Span),
+    While(Box<Expr>, Vec<Stmt>, Span),
+    Break(Span),
+    Continue(Span),
+    Function(String, Vec<(String, Type)>, Option<Type>, Vec<Stmt>, Span),
+    Return(Option<Box<Expr>>, Span),
+    Call(String, Vec<Expr>, Span),
+}
+
+impl Stmt {
+    fn write_tree(&self, f: &mut Formatter<'_>, depth: usize) -> std::fmt::Result {
+        let indent = "    ".repeat(depth);
+        let block = |f: &mut Formatter<'_>, stmts: &[Stmt]| {
+            stmts
+                .iter()
+                .try_for_each(|stmt| stmt.write_tree(f, depth + 1))
+        };
+        match self {
+            Stmt::Var(t, name, value, _) => {
+                writeln!(f, "{indent}ცვლადი {}: {} = {}", name, t, value)
+            }
+            Stmt::Assign(_, name, value, _) => writeln!(f, "{indent}{} = {}", name, value),
+            Stmt::Print(value, _) => writeln!(f, "{indent}დაბეჭდე {}", value),
+            Stmt::Expression(value, _) => writeln!(f, "{indent}{}", value),
+            Stmt::If(condition, then, otherwise, _) => {
+                writeln!(f, "{indent}თუ {}", condition)?;
+                block(f, then)?;
+                if let Some(otherwise) = otherwise {
+                    writeln!(f, "{indent}თორემ")?;
+                    block(f, otherwise)?;
+                }
+                Ok(())
+            }
+            Stmt::While(condition, body, _) => {
+                writeln!(f, "{indent}სანამ {}", condition)?;
+                block(f, body)
+            }
+            Stmt::Break(_) => writeln!(f, "{indent}შეწყვიტე"),
+            Stmt::Continue(_) => writeln!(f, "{indent}გააგრძელე"),
+            Stmt::Function(name, params, return_type, body, _) => {
+                let params: Vec<String> = params
+                    .iter()
+                    .map(|(name, t)| format!("{}: {}", name, t))
+                    .collect();
+                write!(f, "{indent}ფუნქცია {}({})", name, params.join(", "))?;
+                match return_type {
+                    Some(t) => writeln!(f, ": {}", t)?,
+                    None => writeln!(f)?,
+                }
+                block(f, body)
+            }
+            Stmt::Return(Some(value), _) => writeln!(f, "{indent}დააბრუნე {}", value),
+            Stmt::Return(None, _) => writeln!(f, "{indent}დააბრუნე"),
+            Stmt::Call(name, args, _) => {
+                write!(f, "{indent}({}", name)?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                writeln!(f, ")")
+            }
+        }
+    }
+}
+
+/// Writes the statement and any nested blocks one statement per line,
+/// indenting each block by four spaces.
+impl Display for Stmt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.write_tree(f, 0)
+    }
 }
@@ -1,5 +1,48 @@
+use std::fmt::{Display, Formatter};
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Type {
     I32,
+    I64,
     F64,
+    Bool,
+    Str,
+}
+
+impl Type {
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "i32" => Some(Type::I32),
+            "i64" => Some(Type::I64),
+            "f64" => Some(Type::F64),
+            "bool" => Some(Type::Bool),
+            "str" => Some(Type::Str),
+            _ => None,
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Type::I32 | Type::I64)
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Type::I32 | Type::I64 | Type::F64)
+    }
+
+    /// Whether an explicit `როგორც` conversion from `self` to `target` exists.
+    pub fn can_cast_to(&self, target: &Type) -> bool {
+        self == target || (target.is_numeric() && *self != Type::Str)
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::I32 => write!(f, "i32"),
+            Type::I64 => write!(f, "i64"),
+            Type::F64 => write!(f, "f64"),
+            Type::Bool => write!(f, "bool"),
+            Type::Str => write!(f, "str"),
+        }
+    }
 }
@@ -0,0 +1,80 @@
+#![allow(dead_code)]
+
+use aeiou::compiler::Compiler;
+use aeiou::{lexer, parser};
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::{env, fs};
+
+pub fn compile(source: &str) -> String {
+    let tokens = lexer::tokenize(source).unwrap();
+    let statements = parser::parse(tokens).unwrap();
+    Compiler::new().compile(statements).unwrap()
+}
+
+/// Builds `source` into an executable with the `aeiou` driver and runs it,
+/// returning its standard output. `name` must be unique among the tests.
+pub fn run(name: &str, source: &str) -> String {
+    let dir: PathBuf = env::temp_dir().join(format!("aeiou-test-{}-{name}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let source_path = dir.join(format!("{name}.aeiou"));
+    let exe_path = dir.join(name);
+    fs::write(&source_path, source).unwrap();
+
+    let build = Command::new(env!("CARGO_BIN_EXE_aeiou"))
+        .arg("build")
+        .arg(&source_path)
+        .arg("-o")
+        .arg(&exe_path)
+        .output()
+        .unwrap();
+    assert!(
+        build.status.success(),
+        "building '{name}' failed:\n{}",
+        String::from_utf8_lossy(&build.stderr)
+    );
+
+    let output = Command::new(&exe_path).output().unwrap();
+    let _ = fs::remove_dir_all(&dir);
+    assert!(
+        output.status.success(),
+        "'{name}' exited with {}",
+        output.status
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Assembles and links `asm` the way the `aeiou` driver does, runs the
+/// executable and returns what it printed and how it exited. `name` must be
+/// unique among the tests.
+pub fn execute(name: &str, asm: &str) -> Output {
+    let dir: PathBuf = env::temp_dir().join(format!("aeiou-test-{}-{name}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let asm_path = dir.join(format!("{name}.asm"));
+    let object_path = dir.join(format!("{name}.o"));
+    let exe_path = dir.join(name);
+    fs::write(&asm_path, asm).unwrap();
+
+    for command in [
+        Command::new("nasm")
+            .args(["-f", "elf64", "-o"])
+            .arg(&object_path)
+            .arg(&asm_path),
+        Command::new("cc")
+            .arg("-no-pie")
+            .arg("-o")
+            .arg(&exe_path)
+            .arg(&object_path),
+    ] {
+        let output = command.output().unwrap();
+        assert!(
+            output.status.success(),
+            "building '{name}' failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let output = Command::new(&exe_path).output().unwrap();
+    let _ = fs::remove_dir_all(&dir);
+    output
+}
@@ -0,0 +1,52 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs `aeiou` with `args`, feeding `stdin` to it, and returns its exit
+/// status.
+fn status(args: &[&str], stdin: &str) -> Option<i32> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_aeiou"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    // Ignored, since `aeiou` may exit before reading its input.
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    child.wait().unwrap().code()
+}
+
+fn build_status(source: &str) -> Option<i32> {
+    status(&["build", "-", "--emit=asm", "-o", "/dev/null"], source)
+}
+
+#[test]
+fn lexical_errors_exit_with_4() {
+    assert_eq!(build_status("დაბეჭდე 1 $ 2\n"), Some(4));
+}
+
+#[test]
+fn syntax_errors_exit_with_5() {
+    assert_eq!(build_status("დაბეჭდე (1 + 2\n"), Some(5));
+}
+
+#[test]
+fn type_errors_exit_with_6() {
+    assert_eq!(build_status("დაბეჭდე 1 + 1.5\n"), Some(6));
+}
+
+#[test]
+fn usage_errors_exit_with_2() {
+    assert_eq!(status(&["build"], ""), Some(2));
+    assert_eq!(status(&["compile", "-"], ""), Some(2));
+}
+
+#[test]
+fn missing_files_exit_with_3() {
+    assert_eq!(status(&["build", "does-not-exist.aeiou"], ""), Some(3));
+}
+
+#[test]
+fn valid_programs_exit_with_0() {
+    assert_eq!(build_status("დაბეჭდე 1 + 2\n"), Some(0));
+}
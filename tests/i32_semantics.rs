@@ -0,0 +1,30 @@
+mod common;
+
+use common::compile;
+
+#[test]
+fn integer_arithmetic_uses_32_bit_registers() {
+    let asm = compile("ცვლადი x = 7\nდაბეჭდე x * 3 - x / 2 + -x\n");
+    assert!(!asm.contains("movsxd"));
+    let wide = ["rcx", "rdi", "rsi", "r8", "r9", "r10", "r11"];
+    for line in asm.lines().map(str::trim) {
+        let (instruction, operands) = line.split_once(' ').unwrap_or((line, ""));
+        if ["add", "sub", "imul", "neg", "cmp"].contains(&instruction) {
+            assert!(
+                !operands.split(", ").any(|operand| wide.contains(&operand)),
+                "64-bit arithmetic '{line}' in:\n{asm}"
+            );
+        }
+    }
+}
+
+#[test]
+fn division_sign_extends_the_dividend() {
+    let asm = compile("დაბეჭდე -7 / 2\n");
+    let lines: Vec<&str> = asm.lines().map(str::trim).collect();
+    let idiv = lines
+        .iter()
+        .position(|line| line.starts_with("idiv"))
+        .unwrap();
+    assert_eq!(lines[idiv - 1], "cdq");
+}
@@ -0,0 +1,48 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+/// Address space `aeiou` is given, in KiB, about what a small CI container
+/// allows.
+const LIMIT: u32 = 1_000_000;
+
+/// Runs `aeiou run --interpret` on `source` under `LIMIT`.
+fn interpret(source: &str) -> Output {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(format!(
+            "ulimit -v {LIMIT} && exec \"$0\" run --interpret -"
+        ))
+        .arg(env!("CARGO_BIN_EXE_aeiou"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let _ = child.stdin.take().unwrap().write_all(source.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn programs_are_interpreted_with_limited_memory() {
+    let programs = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    let source = std::fs::read_to_string(programs.join("arithmetic.aeiou")).unwrap();
+    let output = interpret(&source);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let expected = std::fs::read(programs.join("arithmetic.out")).unwrap();
+    assert_eq!(output.stdout, expected);
+}
+
+#[test]
+fn runaway_recursion_stops_with_limited_memory() {
+    let output = interpret("ფუნქცია r(n: i32): i32 {\n    დააბრუნე r(n + 1)\n}\nდაბეჭდე r(0)\n");
+    assert_eq!(output.status.code(), Some(101));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "შეცდომა ხაზზე 2: სტეკის გადავსება\n"
+    );
+}
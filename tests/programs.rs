@@ -0,0 +1,217 @@
+//! Runs every program in `tests/programs` and compares it with its golden
+//! files:
+//!
+//! - `name.out` is what the program prints to standard output.
+//! - `name.stderr`, if present, is the runtime error the program stops with,
+//!   which also means it exits with `RUNTIME_ERROR_STATUS`.
+//! - `name.err`, instead of the two above, lists the diagnostics of a program
+//!   that must not compile, one `line:column: message` per line.
+//!
+//! A program whose first line is `// checked` is run with runtime checks. Each
+//! program is interpreted, and also built and executed by a test that is
+//! ignored when nasm was not found, so the two backends are held to the same
+//! output.
+
+mod common;
+
+use aeiou::compiler::{Compiler, RUNTIME_ERROR_STATUS};
+use aeiou::diagnostic::{Diagnostic, ErrorKind};
+use aeiou::interpreter::Interpreter;
+use aeiou::parser::Stmt;
+use aeiou::{lexer, parser};
+use common::execute;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+struct Program {
+    name: String,
+    source: String,
+    checked: bool,
+    path: PathBuf,
+}
+
+impl Program {
+    fn golden(&self, extension: &str) -> Option<String> {
+        fs::read_to_string(self.path.with_extension(extension)).ok()
+    }
+}
+
+fn programs() -> Vec<Program> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "aeiou")
+        })
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let source = fs::read_to_string(&path).unwrap();
+            Program {
+                name: path.file_stem().unwrap().to_string_lossy().into_owned(),
+                checked: source.lines().next() == Some("// checked"),
+                source,
+                path,
+            }
+        })
+        .collect()
+}
+
+fn parse(source: &str) -> Result<Vec<Stmt>, Vec<Diagnostic>> {
+    let tokens = lexer::tokenize(source).map_err(|err| vec![err])?;
+    parser::parse(tokens)
+}
+
+fn format_diagnostics(diagnostics: &[Diagnostic]) -> String {
+    diagnostics
+        .iter()
+        .map(|diagnostic| match diagnostic.span {
+            Some(span) => format!("{}:{}: {}\n", span.line, span.column, diagnostic.message),
+            None => format!("{}\n", diagnostic.message),
+        })
+        .collect()
+}
+
+/// The runtime error line a compiled program prints, for a failed check.
+fn format_runtime_error(diagnostic: &Diagnostic) -> String {
+    let line = diagnostic.span.map_or(0, |span| span.line);
+    format!("შეცდომა ხაზზე {}: {}\n", line, diagnostic.message)
+}
+
+/// Checks a program that must fail to compile against `name.err`.
+fn check_diagnostics(program: &Program, expected: &str) -> Result<(), String> {
+    let diagnostics = match parse(&program.source) {
+        Ok(statements) => match Compiler::new().compile(statements) {
+            Ok(_) => return Err("compiled, but diagnostics were expected".to_string()),
+            Err(err) => vec![err],
+        },
+        Err(errors) => errors,
+    };
+    let actual = format_diagnostics(&diagnostics);
+    match actual == expected {
+        true => Ok(()),
+        false => Err(format!(
+            "diagnostics differ\nexpected:\n{expected}actual:\n{actual}"
+        )),
+    }
+}
+
+/// The expected standard output and standard error of a runnable program.
+fn expected_output(program: &Program) -> Result<(String, String), String> {
+    let stdout = program
+        .golden("out")
+        .ok_or_else(|| "no '.out' or '.err' file".to_string())?;
+    Ok((stdout, program.golden("stderr").unwrap_or_default()))
+}
+
+/// Checks the interpreter against `name.out` and `name.stderr`.
+fn check_interpreted(program: &Program) -> Result<(), String> {
+    let (expected_stdout, expected_stderr) = expected_output(program)?;
+    let statements = parse(&program.source).map_err(|errors| format_diagnostics(&errors))?;
+    let mut stdout = Vec::new();
+    let result = Interpreter::new(&mut stdout)
+        .checked(program.checked)
+        .run(statements);
+    let stderr = match result {
+        Ok(()) => String::new(),
+        Err(err) if err.kind == ErrorKind::Runtime => format_runtime_error(&err),
+        Err(err) => return Err(format_diagnostics(&[err])),
+    };
+    compare(
+        "interpreted",
+        &expected_stdout,
+        &expected_stderr,
+        stdout,
+        stderr,
+    )
+}
+
+/// Checks the compiled program against `name.out` and `name.stderr`.
+fn check_compiled(program: &Program) -> Result<(), String> {
+    let (expected_stdout, expected_stderr) = expected_output(program)?;
+    let statements = parse(&program.source).map_err(|errors| format_diagnostics(&errors))?;
+    let asm = Compiler::new()
+        .checked(program.checked)
+        .compile(statements)
+        .map_err(|err| format_diagnostics(&[err]))?;
+    let output = execute(&format!("program-{}", program.name), &asm);
+    let status = match expected_stderr.is_empty() {
+        true => 0,
+        false => RUNTIME_ERROR_STATUS,
+    };
+    if output.status.code() != Some(status) {
+        return Err(format!(
+            "compiled: expected status {status}, got {}",
+            output.status
+        ));
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    compare(
+        "compiled",
+        &expected_stdout,
+        &expected_stderr,
+        output.stdout,
+        stderr,
+    )
+}
+
+fn compare(
+    backend: &str,
+    expected_stdout: &str,
+    expected_stderr: &str,
+    stdout: Vec<u8>,
+    stderr: String,
+) -> Result<(), String> {
+    let stdout = String::from_utf8_lossy(&stdout);
+    if stdout != expected_stdout {
+        return Err(format!(
+            "{backend}: standard output differs\nexpected:\n{expected_stdout}actual:\n{stdout}"
+        ));
+    }
+    if stderr != expected_stderr {
+        return Err(format!(
+            "{backend}: standard error differs\nexpected:\n{expected_stderr}actual:\n{stderr}"
+        ));
+    }
+    Ok(())
+}
+
+/// Checks every program, diagnostics against `name.err` and runnable ones
+/// with `check`, and reports all failures together.
+fn check_programs(check: fn(&Program) -> Result<(), String>) {
+    let programs = programs();
+    assert!(!programs.is_empty());
+
+    let mut failures = Vec::new();
+    for program in &programs {
+        let result = match program.golden("err") {
+            Some(expected) => check_diagnostics(program, &expected),
+            None => check(program),
+        };
+        if let Err(message) = result {
+            failures.push(format!("{}: {}", program.name, message));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} programs failed:\n\n{}",
+        failures.len(),
+        programs.len(),
+        failures.join("\n\n")
+    );
+}
+
+#[test]
+fn interpreted_programs_match_golden_files() {
+    check_programs(check_interpreted);
+}
+
+#[test]
+#[cfg_attr(not(has_nasm), ignore = "nasm was not found")]
+fn compiled_programs_match_golden_files() {
+    check_programs(check_compiled);
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use common::{compile, run};
+
+/// Follows `rsp` through straight-line code and returns, for every `call`,
+/// the callee together with the number of bytes between `rbp` and `rsp`.
+fn call_depths(asm: &str) -> Vec<(String, usize)> {
+    let mut depth = 0;
+    let mut calls = Vec::new();
+    for line in asm.lines().map(str::trim) {
+        let (instruction, operands) = line.split_once(' ').unwrap_or((line, ""));
+        let bytes = || {
+            operands
+                .rsplit(' ')
+                .next()
+                .unwrap()
+                .parse::<usize>()
+                .unwrap()
+        };
+        match instruction {
+            "mov" if operands == "rbp, rsp" => depth = 0,
+            "sub" if operands.starts_with("rsp,") => depth += bytes(),
+            "add" if operands.starts_with("rsp,") => depth -= bytes(),
+            "lea" if operands.starts_with("rsp,") => {
+                depth = operands
+                    .trim_end_matches(']')
+                    .rsplit(' ')
+                    .next()
+                    .unwrap()
+                    .parse()
+                    .unwrap()
+            }
+            "push" if operands != "rbp" => depth += 8,
+            "pop" => depth -= 8,
+            "call" => calls.push((operands.to_string(), depth)),
+            _ => {}
+        }
+    }
+    calls
+}
+
+fn assert_aligned(source: &str) {
+    let asm = compile(source);
+    let calls = call_depths(&asm);
+    assert!(!calls.is_empty());
+    for (callee, depth) in calls {
+        assert_eq!(depth % 16, 0, "misaligned call to '{callee}':\n{asm}");
+    }
+}
+
+#[test]
+fn float_print_after_odd_number_of_declarations() {
+    assert_aligned("ცვლადი a = 1\nდაბეჭდე 1.5\n");
+    assert_aligned("ცვლადი a = 1\nცვლადი b = 2.5\nცვლადი c = 3\nდაბეჭდე b * 2.0\nდაბეჭდე a + c\n");
+}
+
+#[test]
+#[cfg_attr(not(has_nasm), ignore = "nasm was not found")]
+fn float_print_after_odd_number_of_declarations_runs() {
+    assert_eq!(
+        run("odd_declarations", "ცვლადი a = 1\nდაბეჭდე 1.5\n"),
+        "1.500000\n"
+    );
+}
+
+#[test]
+fn float_print_inside_function_called_mid_expression() {
+    assert_aligned(
+        r#"ფუნქცია half(x: f64): f64 {
+    ცვლადი y = x / 2.0
+    დაბეჭდე y
+    დააბრუნე y
+}
+ცვლადი a = 1.0
+დაბეჭდე 1.0 + (2.0 + half(a))
+"#,
+    );
+}
+
+#[test]
+fn exit_is_aligned() {
+    let asm = compile("ცვლადი a = 1\nთუ a < 2 {\n    ცვლადი b = a\n}\n");
+    let calls = call_depths(&asm);
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].0, "exit");
+    assert_eq!(calls[0].1 % 16, 0);
+}